@@ -1,3 +1,5 @@
+#version 450 core
+
 in vec2 vs_uv;
 
 uniform sampler2D tex;
@@ -1,7 +1,18 @@
-in vec4 vs_rgba;
+#version 450 core
+
+#include "constants.glsl"
+
+in vec4 vs_tint;
+in vec4 vs_flash;
 in vec2 vs_uv;
 in vec2 vs_pos;
+in vec2 vs_local_pos;
+flat in vec2 vs_size;
+flat in vec4 vs_tex_uvwh;
+flat in vec4 vs_slice_insets;
+flat in float vs_slice_scale;
 flat in uint vs_tex_id;
+flat in uvec2 vs_tex_page_layer;
 flat in uint vs_effect;
 
 struct Light {
@@ -10,10 +21,10 @@ struct Light {
     vec3 attenuation;
 };
 
-uniform Light lights[32];
+uniform Light lights[MAX_N_LIGHTS];
 uniform int n_lights;
-uniform sampler2D sprite_atlas_tex;
-uniform sampler2D glyph_atlas_tex;
+uniform uint blend_mode;
+uniform sampler2DArray texture_pages[MAX_N_BOUND_TEXTURE_PAGES];
 
 out vec4 frag_color;
 
@@ -21,26 +32,72 @@ vec2 random2(vec2 p) {
     return fract(sin(vec2(dot(p, vec2(127.1, 311.7)), dot(p, vec2(269.5, 183.3)))) * 43758.5453);
 }
 
+// Samplers can't be indexed by the per-primitive value, so each bound
+// page gets its own branch
+#define SAMPLE_PAGE(i) case i: { \
+    vec2 page_size = vec2(textureSize(texture_pages[i], 0).xy); \
+    return texture(texture_pages[i], vec3(uv / page_size, layer)); \
+}
+
+vec4 sample_texture(vec2 uv) {
+    float layer = float(vs_tex_page_layer.y);
+
+    switch (vs_tex_page_layer.x) {
+        SAMPLE_PAGE(0)
+        SAMPLE_PAGE(1)
+        SAMPLE_PAGE(2)
+        SAMPLE_PAGE(3)
+        SAMPLE_PAGE(4)
+        SAMPLE_PAGE(5)
+        SAMPLE_PAGE(6)
+        SAMPLE_PAGE(7)
+    }
+
+    return vec4(0.0);
+}
+
+// Maps the position in the rect to the sprite pixel, so the borders
+// keep their size and the middle parts are stretched. The insets are
+// (left, bottom, right, top)
+vec2 get_nine_slice_uv() {
+    vec2 tex_size = vs_tex_uvwh.zw;
+    vec2 inset_min = vs_slice_insets.xy;
+    vec2 inset_max = vs_slice_insets.zw;
+    vec2 border_min = inset_min * vs_slice_scale;
+    vec2 border_max = inset_max * vs_slice_scale;
+
+    vec2 pos = vs_local_pos;
+    vec2 middle_size = max(vs_size - border_min - border_max, 1e-4);
+    vec2 tex_middle_size = tex_size - inset_min - inset_max;
+    vec2 middle_k = clamp((pos - border_min) / middle_size, 0.0, 1.0);
+
+    vec2 tex_pos = inset_min + middle_k * tex_middle_size;
+    tex_pos = mix(tex_pos, pos / vs_slice_scale, lessThan(pos, border_min));
+    tex_pos = mix(
+        tex_pos,
+        tex_size - (vs_size - pos) / vs_slice_scale,
+        greaterThan(pos, vs_size - border_max)
+    );
+
+    return vs_tex_uvwh.xy + vec2(tex_pos.x, tex_pos.y - tex_size.y);
+}
+
 vec4 get_color() {
     vec4 color;
 
-    if (vs_tex_id == SpriteTexture) {
-        vec2 tex_size = vec2(textureSize(sprite_atlas_tex, 0));
+    if (vs_tex_id == SpriteTexture || vs_tex_id == NineSliceTexture) {
         vec2 uv = vs_uv;
+        if (vs_tex_id == NineSliceTexture) {
+            uv = get_nine_slice_uv();
+        }
         uv = vec2(floor(uv.x), ceil(uv.y)) + min(fract(uv) / fwidth(uv), 1.0) - 0.5;
-        uv /= tex_size;
-        color = texture(sprite_atlas_tex, uv);
-        color = vec4(color.rgb + vs_rgba.rgb, vs_rgba.a * color.a);
+        color = sample_texture(uv) * vs_tint;
     } else if (vs_tex_id == GlyphTexture) {
-        vec2 tex_size = vec2(textureSize(glyph_atlas_tex, 0));
-        vec2 uv = vs_uv;
-        uv /= tex_size;
-
-        float alpha = texture(glyph_atlas_tex, uv).r;
-        alpha *= vs_rgba.a;
-        color = vec4(vs_rgba.rgb, alpha);
+        float alpha = sample_texture(vs_uv).r;
+        alpha *= vs_tint.a;
+        color = vec4(vs_tint.rgb, alpha);
     } else {
-        color = vs_rgba;
+        color = vs_tint;
     }
 
     return color;
@@ -102,6 +159,13 @@ void main(void) {
         color = apply_light(color);
     }
 
+    color.rgb += vs_flash.rgb * vs_flash.a;
+
+    // Blending expects premultiplied colors
+    if (blend_mode != Premultiplied) {
+        color.rgb *= color.a;
+    }
+
     frag_color = color;
 }
 
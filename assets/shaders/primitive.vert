@@ -1,18 +1,26 @@
+#version 450 core
+
+#include "common.glsl"
+#include "constants.glsl"
+
+// Instance attributes (a_*) are generated from the renderer
+// PrimitiveInstance struct
+#include "instance_attributes.glsl"
+
 uniform vec4 camera_xywh;
 uniform vec2 screen_size;
 
-layout (location = 0) in vec4 a_xywh;
-layout (location = 1) in uint a_space;
-layout (location = 2) in uint a_effect;
-layout (location = 3) in vec4 a_tex_uvwh;
-layout (location = 4) in vec4 a_rgba;
-layout (location = 5) in uint a_tex_id;
-layout (location = 6) in float a_flip;
-
 flat out uint vs_tex_id;
+flat out uvec2 vs_tex_page_layer;
 flat out uint vs_effect;
-out vec4 vs_rgba;
+out vec4 vs_tint;
+out vec4 vs_flash;
 out vec2 vs_uv;
+out vec2 vs_local_pos;
+flat out vec2 vs_size;
+flat out vec4 vs_tex_uvwh;
+flat out vec4 vs_slice_insets;
+flat out float vs_slice_scale;
 out vec2 vs_pos;
 
 vec2 project(vec2 pos) {
@@ -36,18 +44,29 @@ void main(void) {
     vec2 size = a_xywh.zw;
 
     pos += 0.5 * RECT_IDX_TO_NDC[gl_VertexID] * size;
+    pos = mat2(a_transform.xy, a_transform.zw) * pos + a_translation;
     vec2 proj = project(pos);
 
     vec2 local_uv = RECT_IDX_TO_UV[gl_VertexID];
     local_uv.y = 1.0 - local_uv.y;
-    if (a_flip > 0.0) {
+    if (a_flip.x > 0.0) {
         local_uv.x = 1.0 - local_uv.x;
     }
+    if (a_flip.y > 0.0) {
+        local_uv.y = 1.0 - local_uv.y;
+    }
     vs_uv = a_tex_uvwh.xy + local_uv * vec2(a_tex_uvwh.z, -a_tex_uvwh.w);
+    vs_local_pos = vec2(local_uv.x, 1.0 - local_uv.y) * size;
+    vs_size = size;
+    vs_tex_uvwh = a_tex_uvwh;
+    vs_slice_insets = a_slice_insets;
+    vs_slice_scale = a_slice_scale;
 
     vs_tex_id = a_tex_id;
+    vs_tex_page_layer = a_tex_page_layer;
     vs_effect = a_effect;
-    vs_rgba = a_rgba;
+    vs_tint = a_tint;
+    vs_flash = a_flash;
     vs_pos = pos;
     gl_Position = vec4(proj, 0.0, 1.0);
 }
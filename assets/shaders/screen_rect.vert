@@ -1,3 +1,7 @@
+#version 450 core
+
+#include "common.glsl"
+
 out vec2 vs_uv;
 
 
@@ -0,0 +1,111 @@
+use crate::error::{Error, Result};
+use crate::frame::{FrameAnimator, FrameAtlas};
+
+/// Handle of the atlas owned by `Assets`. The generation makes the
+/// handle invalid after the atlas is unloaded, even if its slot is reused.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct AtlasHandle {
+    idx: usize,
+    generation: u32,
+}
+
+struct AtlasSlot {
+    generation: u32,
+    atlas: Option<FrameAtlas>,
+}
+
+pub struct Assets {
+    atlas_slots: Vec<AtlasSlot>,
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self {
+            atlas_slots: Vec::new(),
+        }
+    }
+
+    pub fn load_atlas(&mut self, meta_fp: &str) -> Result<AtlasHandle> {
+        Ok(self.insert_atlas(FrameAtlas::new(meta_fp)?))
+    }
+
+    pub fn insert_atlas(&mut self, atlas: FrameAtlas) -> AtlasHandle {
+        let free_idx = self
+            .atlas_slots
+            .iter()
+            .position(|slot| slot.atlas.is_none());
+        let idx = match free_idx {
+            Some(idx) => idx,
+            None => {
+                self.atlas_slots.push(AtlasSlot {
+                    generation: 0,
+                    atlas: None,
+                });
+                self.atlas_slots.len() - 1
+            }
+        };
+
+        let slot = &mut self.atlas_slots[idx];
+        slot.atlas = Some(atlas);
+
+        AtlasHandle {
+            idx,
+            generation: slot.generation,
+        }
+    }
+
+    pub fn unload_atlas(&mut self, handle: AtlasHandle) {
+        if self.check_if_loaded(handle) {
+            let slot = &mut self.atlas_slots[handle.idx];
+            slot.atlas = None;
+            slot.generation += 1;
+        }
+    }
+
+    pub fn check_if_loaded(&self, handle: AtlasHandle) -> bool {
+        self.atlas_slots.get(handle.idx).is_some_and(|slot| {
+            slot.generation == handle.generation && slot.atlas.is_some()
+        })
+    }
+
+    pub fn get_atlas(&self, handle: AtlasHandle) -> Result<&FrameAtlas> {
+        self.atlas_slots
+            .get(handle.idx)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.atlas.as_ref())
+            .ok_or(Error::UnloadedAtlas)
+    }
+
+    pub fn new_animator(
+        &self,
+        handle: AtlasHandle,
+    ) -> Result<FrameAnimator> {
+        self.get_atlas(handle)?;
+
+        Ok(FrameAnimator::new(handle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_atlas() -> FrameAtlas {
+        serde_json::from_str(r#"{"size": [1, 1], "frames": {}}"#).unwrap()
+    }
+
+    #[test]
+    fn test_unloaded_atlas_handle_is_stale() {
+        let mut assets = Assets::new();
+        let handle = assets.insert_atlas(new_atlas());
+        assets.unload_atlas(handle);
+        let new_handle = assets.insert_atlas(new_atlas());
+
+        assert!(assets.get_atlas(new_handle).is_ok());
+        assert!(matches!(
+            assets.get_atlas(handle),
+            Err(Error::UnloadedAtlas)
+        ));
+        assert!(assets.new_animator(handle).is_err());
+    }
+}
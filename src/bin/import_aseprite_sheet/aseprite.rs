@@ -0,0 +1,801 @@
+//! Reader of the binary `.aseprite` files, see
+//! https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md
+//!
+//! Only the parts needed for the atlas are read: layers, cels (raw,
+//! linked and compressed), tags, frame durations, palettes, nine-patch
+//! slices and user data of the cels. Tilemap cels are skipped.
+use crate::{
+    get_insets, get_mask, parse_events, Result, SliceKey, Sprite,
+};
+use crate::{MASK_LAYER_PREFIX, SPRITE_LAYER};
+use flate2::read::ZlibDecoder;
+use image::{Rgba, RgbaImage};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+const FILE_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+
+const OLD_PALETTE_CHUNK: u16 = 0x0004;
+const LAYER_CHUNK: u16 = 0x2004;
+const CEL_CHUNK: u16 = 0x2005;
+const CEL_EXTRA_CHUNK: u16 = 0x2006;
+const TAGS_CHUNK: u16 = 0x2018;
+const PALETTE_CHUNK: u16 = 0x2019;
+const USER_DATA_CHUNK: u16 = 0x2020;
+const SLICE_CHUNK: u16 = 0x2022;
+
+const GROUP_LAYER: u16 = 1;
+const LAYER_OPACITY_FLAG: u32 = 1;
+const USER_DATA_TEXT_FLAG: u32 = 1;
+const NINE_PATCH_SLICE_FLAG: u32 = 1;
+const SLICE_PIVOT_FLAG: u32 = 2;
+
+const RAW_CEL: u16 = 0;
+const LINKED_CEL: u16 = 1;
+const COMPRESSED_CEL: u16 = 2;
+const COMPRESSED_TILEMAP_CEL: u16 = 3;
+
+struct AsepriteFile {
+    width: u32,
+    height: u32,
+    layers: Vec<Layer>,
+    frames: Vec<Frame>,
+    tags: Vec<Tag>,
+    /// Keys of the first nine-patch slice
+    slice_keys: Vec<SliceKey>,
+}
+
+struct Layer {
+    name: String,
+    kind: u16,
+    opacity: u8,
+}
+
+struct Frame {
+    duration: u16,
+    cels: Vec<Cel>,
+}
+
+struct Cel {
+    layer_idx: usize,
+    x: i32,
+    y: i32,
+    opacity: u8,
+    content: CelContent,
+    user_data: String,
+}
+
+enum CelContent {
+    Image(RgbaImage),
+    Linked(usize),
+}
+
+struct Tag {
+    name: String,
+    from: usize,
+    to: usize,
+}
+
+/// Chunk which the next user data chunk belongs to.
+#[derive(Copy, Clone)]
+enum UserDataTarget {
+    Nothing,
+    Cel(usize),
+}
+
+/// Reads the sprites of the `.aseprite` file. Animations are named
+/// `{title}_{tag}`, frames without tags belong to the `{title}`.
+pub fn load_aseprite(fp: &Path) -> Result<Vec<Sprite>> {
+    let bytes = fs::read(fp)
+        .map_err(|e| format!("Can't read {}: {}", fp.display(), e))?;
+    let file = AsepriteFile::parse(&bytes)
+        .map_err(|e| format!("Can't parse {}: {}", fp.display(), e))?;
+    let title = fp.file_stem().unwrap().to_string_lossy();
+
+    for layer in &file.layers {
+        if layer.kind != GROUP_LAYER
+            && layer.name != SPRITE_LAYER
+            && !layer.name.starts_with(MASK_LAYER_PREFIX)
+        {
+            return Err(format!(
+                "{}: layer name {} is not valid. The layer name should \
+                 be `sprite` (which represents the actual sprite) or \
+                 start with `mask_`",
+                fp.display(),
+                layer.name
+            ));
+        }
+    }
+    let sprite_layer_idx = file
+        .layers
+        .iter()
+        .position(|layer| layer.name == SPRITE_LAYER)
+        .ok_or_else(|| {
+            format!("{} is missing the `sprite` layer", fp.display())
+        })?;
+
+    let mut animations = Vec::new();
+    let mut is_tagged = vec![false; file.frames.len()];
+    for tag in &file.tags {
+        if tag.from >= file.frames.len() {
+            continue;
+        }
+        let to = tag.to.min(file.frames.len().saturating_sub(1));
+        is_tagged[tag.from..=to].fill(true);
+        animations
+            .push((format!("{}_{}", title, tag.name), tag.from..=to));
+    }
+    let untagged = is_tagged.iter().position(|is_tagged| !is_tagged);
+    if let Some(from) = untagged {
+        let to =
+            is_tagged.iter().rposition(|is_tagged| !is_tagged).unwrap();
+        animations.push((title.to_string(), from..=to));
+    }
+
+    let mut sprites = Vec::new();
+    for (name, frame_idxs) in animations {
+        for frame_idx in frame_idxs {
+            if is_tagged[frame_idx] && name == title {
+                continue;
+            }
+
+            let frame = &file.frames[frame_idx];
+            let sprite_cel = file.get_cel(frame_idx, sprite_layer_idx);
+            let mut masks = BTreeMap::new();
+            for (layer_idx, layer) in file.layers.iter().enumerate() {
+                let Some(mask_name) =
+                    layer.name.strip_prefix(MASK_LAYER_PREFIX)
+                else {
+                    continue;
+                };
+                let image = file.get_layer_image(frame_idx, layer_idx);
+                if let Some(mask) = get_mask(&image) {
+                    masks.insert(mask_name.to_string(), mask);
+                }
+            }
+
+            sprites.push(Sprite {
+                name: name.clone(),
+                frame_idx,
+                image: file.get_layer_image(frame_idx, sprite_layer_idx),
+                // Aseprite frame durations are in milliseconds
+                duration: frame.duration as f32 / 1000.0,
+                events: sprite_cel
+                    .map(|cel| parse_events(&cel.user_data))
+                    .unwrap_or_default(),
+                masks,
+                insets: get_insets(
+                    &file.slice_keys,
+                    frame_idx,
+                    file.width,
+                    file.height,
+                ),
+            });
+        }
+    }
+
+    Ok(sprites)
+}
+
+impl AsepriteFile {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+
+        // Header
+        reader.skip(4)?;
+        if reader.read_u16()? != FILE_MAGIC {
+            return Err("Not an aseprite file".to_string());
+        }
+        let n_frames = reader.read_u16()? as usize;
+        let width = reader.read_u16()? as u32;
+        let height = reader.read_u16()? as u32;
+        let color_depth = reader.read_u16()?;
+        let flags = reader.read_u32()?;
+        reader.skip(10)?;
+        let transparent_idx = reader.read_u8()?;
+        reader.skip(128 - 29)?;
+
+        let mut file = Self {
+            width,
+            height,
+            layers: Vec::new(),
+            frames: Vec::new(),
+            tags: Vec::new(),
+            slice_keys: Vec::new(),
+        };
+        let mut palette = vec![Rgba([0, 0, 0, 0]); 256];
+        let is_layer_opacity_valid = flags & LAYER_OPACITY_FLAG != 0;
+
+        for _ in 0..n_frames {
+            let frame_start = reader.pos;
+            let frame_size = reader.read_u32()? as usize;
+            if reader.read_u16()? != FRAME_MAGIC {
+                return Err("Invalid frame magic number".to_string());
+            }
+            let old_n_chunks = reader.read_u16()? as usize;
+            let duration = reader.read_u16()?;
+            reader.skip(2)?;
+            let n_chunks = match reader.read_u32()? as usize {
+                0 => old_n_chunks,
+                n_chunks => n_chunks,
+            };
+
+            let mut frame = Frame {
+                duration,
+                cels: Vec::new(),
+            };
+            let mut user_data_target = UserDataTarget::Nothing;
+            for _ in 0..n_chunks {
+                let chunk_start = reader.pos;
+                let chunk_size = reader.read_u32()? as usize;
+                let chunk_type = reader.read_u16()?;
+                let mut chunk = Reader::new(
+                    reader.read_bytes(chunk_size.saturating_sub(6))?,
+                );
+
+                match chunk_type {
+                    LAYER_CHUNK => {
+                        chunk.skip(2)?;
+                        let kind = chunk.read_u16()?;
+                        chunk.skip(8)?;
+                        let opacity = chunk.read_u8()?;
+                        chunk.skip(3)?;
+                        file.layers.push(Layer {
+                            name: chunk.read_string()?,
+                            kind,
+                            opacity: if is_layer_opacity_valid {
+                                opacity
+                            } else {
+                                255
+                            },
+                        });
+                        user_data_target = UserDataTarget::Nothing;
+                    }
+                    CEL_CHUNK => {
+                        let cel = read_cel(
+                            &mut chunk,
+                            color_depth,
+                            &palette,
+                            transparent_idx,
+                        )?;
+                        user_data_target = match cel {
+                            Some(cel) => {
+                                frame.cels.push(cel);
+                                UserDataTarget::Cel(frame.cels.len() - 1)
+                            }
+                            None => UserDataTarget::Nothing,
+                        };
+                    }
+                    CEL_EXTRA_CHUNK => {}
+                    TAGS_CHUNK => {
+                        let n_tags = chunk.read_u16()?;
+                        chunk.skip(8)?;
+                        for _ in 0..n_tags {
+                            let from = chunk.read_u16()? as usize;
+                            let to = chunk.read_u16()? as usize;
+                            chunk.skip(13)?;
+                            let name = chunk.read_string()?;
+                            file.tags.push(Tag { name, from, to });
+                        }
+                        user_data_target = UserDataTarget::Nothing;
+                    }
+                    PALETTE_CHUNK => {
+                        let size = chunk.read_u32()? as usize;
+                        let first = chunk.read_u32()? as usize;
+                        let last = chunk.read_u32()? as usize;
+                        chunk.skip(8)?;
+                        palette.resize(
+                            palette.len().max(size).max(last + 1),
+                            Rgba([0; 4]),
+                        );
+                        for color in &mut palette[first..=last] {
+                            let entry_flags = chunk.read_u16()?;
+                            let rgba = chunk.read_bytes(4)?;
+                            *color =
+                                Rgba([rgba[0], rgba[1], rgba[2], rgba[3]]);
+                            if entry_flags & 1 != 0 {
+                                chunk.read_string()?;
+                            }
+                        }
+                    }
+                    OLD_PALETTE_CHUNK => {
+                        let n_packets = chunk.read_u16()?;
+                        let mut idx = 0;
+                        for _ in 0..n_packets {
+                            idx += chunk.read_u8()? as usize;
+                            let n_colors = match chunk.read_u8()? {
+                                0 => 256,
+                                n_colors => n_colors as usize,
+                            };
+                            for _ in 0..n_colors {
+                                let rgb = chunk.read_bytes(3)?;
+                                if idx < palette.len() {
+                                    palette[idx] = Rgba([
+                                        rgb[0], rgb[1], rgb[2], 255,
+                                    ]);
+                                }
+                                idx += 1;
+                            }
+                        }
+                    }
+                    USER_DATA_CHUNK => {
+                        let user_data_flags = chunk.read_u32()?;
+                        if let UserDataTarget::Cel(idx) = user_data_target
+                        {
+                            if user_data_flags & USER_DATA_TEXT_FLAG != 0 {
+                                frame.cels[idx].user_data =
+                                    chunk.read_string()?;
+                            }
+                        }
+                    }
+                    SLICE_CHUNK => {
+                        let n_keys = chunk.read_u32()?;
+                        let slice_flags = chunk.read_u32()?;
+                        chunk.skip(4)?;
+                        chunk.read_string()?;
+
+                        let is_nine_patch =
+                            slice_flags & NINE_PATCH_SLICE_FLAG != 0;
+                        let is_first_nine_patch =
+                            is_nine_patch && file.slice_keys.is_empty();
+                        for _ in 0..n_keys {
+                            let frame_idx = chunk.read_u32()? as usize;
+                            let x = chunk.read_i32()?;
+                            let y = chunk.read_i32()?;
+                            chunk.skip(8)?;
+                            if is_nine_patch {
+                                let key = SliceKey {
+                                    frame_idx,
+                                    x,
+                                    y,
+                                    center_x: chunk.read_i32()?,
+                                    center_y: chunk.read_i32()?,
+                                    center_w: chunk.read_u32()?,
+                                    center_h: chunk.read_u32()?,
+                                };
+                                if is_first_nine_patch {
+                                    file.slice_keys.push(key);
+                                }
+                            }
+                            if slice_flags & SLICE_PIVOT_FLAG != 0 {
+                                chunk.skip(8)?;
+                            }
+                        }
+                        user_data_target = UserDataTarget::Nothing;
+                    }
+                    _ => user_data_target = UserDataTarget::Nothing,
+                }
+
+                reader.pos = chunk_start + chunk_size;
+            }
+
+            file.frames.push(frame);
+            reader.pos = frame_start + frame_size;
+        }
+
+        Ok(file)
+    }
+
+    /// Cel of the layer on the frame with the linked cels resolved.
+    fn get_cel(&self, frame_idx: usize, layer_idx: usize) -> Option<&Cel> {
+        let cel = self
+            .frames
+            .get(frame_idx)?
+            .cels
+            .iter()
+            .find(|cel| cel.layer_idx == layer_idx)?;
+
+        match cel.content {
+            CelContent::Image(_) => Some(cel),
+            CelContent::Linked(linked_frame_idx) => {
+                if linked_frame_idx == frame_idx {
+                    return None;
+                }
+                self.get_cel(linked_frame_idx, layer_idx)
+            }
+        }
+    }
+
+    /// The whole canvas image of the layer on the frame, transparent if
+    /// the layer doesn't have a cel on it.
+    fn get_layer_image(
+        &self,
+        frame_idx: usize,
+        layer_idx: usize,
+    ) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width, self.height);
+        let Some(cel) = self.get_cel(frame_idx, layer_idx) else {
+            return image;
+        };
+        let CelContent::Image(cel_image) = &cel.content else {
+            return image;
+        };
+
+        let opacity =
+            cel.opacity as u32 * self.layers[layer_idx].opacity as u32;
+        for (x, y, pixel) in cel_image.enumerate_pixels() {
+            let x = cel.x + x as i32;
+            let y = cel.y + y as i32;
+            if x < 0
+                || y < 0
+                || x >= self.width as i32
+                || y >= self.height as i32
+            {
+                continue;
+            }
+
+            let mut pixel = *pixel;
+            pixel[3] = (pixel[3] as u32 * opacity / (255 * 255)) as u8;
+            image.put_pixel(x as u32, y as u32, pixel);
+        }
+
+        image
+    }
+}
+
+/// Returns `None` for the tilemap cels, they are not used in the atlas.
+fn read_cel(
+    chunk: &mut Reader,
+    color_depth: u16,
+    palette: &[Rgba<u8>],
+    transparent_idx: u8,
+) -> Result<Option<Cel>> {
+    let layer_idx = chunk.read_u16()? as usize;
+    let x = chunk.read_i16()? as i32;
+    let y = chunk.read_i16()? as i32;
+    let opacity = chunk.read_u8()?;
+    let cel_type = chunk.read_u16()?;
+    chunk.skip(7)?;
+
+    let content = match cel_type {
+        LINKED_CEL => CelContent::Linked(chunk.read_u16()? as usize),
+        RAW_CEL | COMPRESSED_CEL => {
+            let w = chunk.read_u16()? as u32;
+            let h = chunk.read_u16()? as u32;
+            let data = chunk.read_bytes(chunk.bytes.len() - chunk.pos)?;
+            let pixels = if cel_type == COMPRESSED_CEL {
+                let mut pixels = Vec::new();
+                ZlibDecoder::new(data)
+                    .read_to_end(&mut pixels)
+                    .map_err(|e| format!("Can't decompress cel: {}", e))?;
+                pixels
+            } else {
+                data.to_vec()
+            };
+
+            CelContent::Image(decode_pixels(
+                &pixels,
+                w,
+                h,
+                color_depth,
+                palette,
+                transparent_idx,
+            )?)
+        }
+        COMPRESSED_TILEMAP_CEL => return Ok(None),
+        _ => {
+            return Err(format!("Unsupported cel type: {}", cel_type));
+        }
+    };
+
+    Ok(Some(Cel {
+        layer_idx,
+        x,
+        y,
+        opacity,
+        content,
+        user_data: String::new(),
+    }))
+}
+
+fn decode_pixels(
+    pixels: &[u8],
+    w: u32,
+    h: u32,
+    color_depth: u16,
+    palette: &[Rgba<u8>],
+    transparent_idx: u8,
+) -> Result<RgbaImage> {
+    let n_bytes = (color_depth / 8) as usize;
+    if !matches!(n_bytes, 1 | 2 | 4) {
+        return Err(format!("Unsupported color depth: {}", color_depth));
+    }
+    if pixels.len() < (w * h) as usize * n_bytes {
+        return Err("Cel is smaller than its size".to_string());
+    }
+
+    let mut image = RgbaImage::new(w, h);
+    for (pixel, bytes) in
+        image.pixels_mut().zip(pixels.chunks_exact(n_bytes))
+    {
+        *pixel = match bytes {
+            [r, g, b, a] => Rgba([*r, *g, *b, *a]),
+            [v, a] => Rgba([*v, *v, *v, *a]),
+            [idx] if *idx == transparent_idx => Rgba([0, 0, 0, 0]),
+            [idx] => palette
+                .get(*idx as usize)
+                .copied()
+                .unwrap_or(Rgba([0, 0, 0, 0])),
+            _ => unreachable!(),
+        };
+    }
+
+    Ok(image)
+}
+
+/// Little-endian reader of the aseprite data types.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn read_bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| "Unexpected end of data".to_string())?;
+        self.pos += n;
+
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<()> {
+        self.read_bytes(n).map(|_| ())
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_i16(&mut self) -> Result<i16> {
+        Ok(self.read_u16()? as i16)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        let len = self.read_u16()? as usize;
+        let bytes = self.read_bytes(len)?;
+
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    /// Little-endian writer of the aseprite data types.
+    #[derive(Default)]
+    struct Writer {
+        bytes: Vec<u8>,
+    }
+
+    impl Writer {
+        fn u8(mut self, v: u8) -> Self {
+            self.bytes.push(v);
+            self
+        }
+
+        fn u16(mut self, v: u16) -> Self {
+            self.bytes.extend(v.to_le_bytes());
+            self
+        }
+
+        fn u32(mut self, v: u32) -> Self {
+            self.bytes.extend(v.to_le_bytes());
+            self
+        }
+
+        fn zeros(mut self, n: usize) -> Self {
+            self.bytes.resize(self.bytes.len() + n, 0);
+            self
+        }
+
+        fn bytes(mut self, bytes: &[u8]) -> Self {
+            self.bytes.extend(bytes);
+            self
+        }
+
+        fn string(self, s: &str) -> Self {
+            self.u16(s.len() as u16).bytes(s.as_bytes())
+        }
+
+        fn chunk(self, chunk_type: u16, data: Writer) -> Self {
+            self.u32(data.bytes.len() as u32 + 6)
+                .u16(chunk_type)
+                .bytes(&data.bytes)
+        }
+    }
+
+    fn layer(name: &str) -> Writer {
+        Writer::default()
+            .zeros(2)
+            .u16(0)
+            .zeros(8)
+            .u8(255)
+            .zeros(3)
+            .string(name)
+    }
+
+    fn cel_header(
+        layer_idx: u16,
+        x: u16,
+        y: u16,
+        cel_type: u16,
+    ) -> Writer {
+        Writer::default()
+            .u16(layer_idx)
+            .u16(x)
+            .u16(y)
+            .u8(255)
+            .u16(cel_type)
+            .zeros(7)
+    }
+
+    fn raw_cel(layer_idx: u16, x: u16, y: u16, w: u16, h: u16) -> Writer {
+        cel_header(layer_idx, x, y, RAW_CEL)
+            .u16(w)
+            .u16(h)
+            .bytes(&RED.repeat((w * h) as usize))
+    }
+
+    fn compressed_cel(
+        layer_idx: u16,
+        x: u16,
+        y: u16,
+        w: u16,
+        h: u16,
+    ) -> Writer {
+        let mut encoder =
+            ZlibEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&RED.repeat((w * h) as usize)).unwrap();
+        cel_header(layer_idx, x, y, COMPRESSED_CEL)
+            .u16(w)
+            .u16(h)
+            .bytes(&encoder.finish().unwrap())
+    }
+
+    fn user_data(text: &str) -> Writer {
+        Writer::default().u32(USER_DATA_TEXT_FLAG).string(text)
+    }
+
+    fn frame(duration: u16, chunks: Writer, n_chunks: u32) -> Writer {
+        Writer::default()
+            .u32(chunks.bytes.len() as u32 + 16)
+            .u16(FRAME_MAGIC)
+            .u16(n_chunks as u16)
+            .u16(duration)
+            .zeros(2)
+            .u32(n_chunks)
+            .bytes(&chunks.bytes)
+    }
+
+    /// 4x4 RGBA file with the `sprite` and `mask_attack` layers. The
+    /// `idle` tag covers the first two frames, the last one is untagged.
+    fn synthetic_file() -> Vec<u8> {
+        let frames = [
+            frame(
+                100,
+                Writer::default()
+                    .chunk(LAYER_CHUNK, layer("sprite"))
+                    .chunk(LAYER_CHUNK, layer("mask_attack"))
+                    .chunk(
+                        TAGS_CHUNK,
+                        Writer::default()
+                            .u16(1)
+                            .zeros(8)
+                            .u16(0)
+                            .u16(1)
+                            .zeros(13)
+                            .string("idle"),
+                    )
+                    .chunk(CEL_CHUNK, raw_cel(0, 0, 0, 4, 4))
+                    .chunk(USER_DATA_CHUNK, user_data("attack, step"))
+                    .chunk(CEL_CHUNK, compressed_cel(1, 1, 2, 2, 1)),
+                6,
+            ),
+            frame(
+                150,
+                Writer::default()
+                    .chunk(
+                        CEL_CHUNK,
+                        cel_header(0, 0, 0, LINKED_CEL).u16(0),
+                    )
+                    .chunk(
+                        CEL_CHUNK,
+                        cel_header(1, 0, 0, COMPRESSED_TILEMAP_CEL)
+                            .zeros(32),
+                    )
+                    .chunk(USER_DATA_CHUNK, user_data("tilemap")),
+                3,
+            ),
+            frame(
+                200,
+                Writer::default().chunk(CEL_CHUNK, raw_cel(0, 1, 1, 2, 2)),
+                1,
+            ),
+        ];
+
+        let frames =
+            frames.into_iter().fold(Writer::default(), |file, frame| {
+                file.bytes(&frame.bytes)
+            });
+        Writer::default()
+            .u32(frames.bytes.len() as u32 + 128)
+            .u16(FILE_MAGIC)
+            .u16(3)
+            .u16(4)
+            .u16(4)
+            .u16(32)
+            .u32(LAYER_OPACITY_FLAG)
+            .zeros(10)
+            .u8(0)
+            .zeros(99)
+            .bytes(&frames.bytes)
+            .bytes
+    }
+
+    #[test]
+    fn test_load_synthetic_file() {
+        let dir = Path::new("./target/aseprite");
+        let fp = dir.join("knight.aseprite");
+        fs::create_dir_all(dir).unwrap();
+        fs::write(&fp, synthetic_file()).unwrap();
+
+        let sprites = load_aseprite(&fp).unwrap();
+        let names: Vec<_> = sprites
+            .iter()
+            .map(|s| (s.name.as_str(), s.frame_idx))
+            .collect();
+        assert_eq!(
+            names,
+            [("knight_idle", 0), ("knight_idle", 1), ("knight", 2)]
+        );
+
+        let durations: Vec<_> =
+            sprites.iter().map(|s| s.duration).collect();
+        assert_eq!(durations, [0.1, 0.15, 0.2]);
+
+        // The linked cel shares the user data of its source cel, the
+        // tilemap cel is skipped together with its user data
+        let events: Vec<_> =
+            sprites.iter().map(|s| s.events.clone()).collect();
+        let attack_events = vec!["attack", "step"];
+        assert_eq!(events, [attack_events.clone(), attack_events, vec![]]);
+
+        let mask = sprites[0].masks["attack"];
+        assert_eq!((mask.x, mask.y, mask.w, mask.h), (1, 2, 2, 1));
+        assert!(sprites[1].masks.is_empty());
+        assert!(sprites[2].masks.is_empty());
+
+        // The linked cel shows the image of the first frame
+        assert_eq!(sprites[1].image.get_pixel(0, 0).0, RED);
+        assert_eq!(sprites[2].image.get_pixel(0, 0).0, [0, 0, 0, 0]);
+        assert_eq!(sprites[2].image.get_pixel(1, 1).0, RED);
+    }
+}
@@ -0,0 +1,610 @@
+//! Packs the Aseprite sprites into the sprite atlas.
+//!
+//! The `.aseprite` files are read directly. The sheet exports are read
+//! as well: each sprite should be exported with split layers and tags,
+//! with the `{title}.{layer}.{tag}.{frame}` filename format and the
+//! json-array meta. The `sprite` layer is the actual sprite, the
+//! `mask_*` layers are converted to the mask rects. The first
+//! nine-patch slice gives the nine-slice insets of the frames.
+//!
+//! Usage: import_aseprite_sheet [--watch] [ASEPRITE_DIR] [OUT_DIR]
+//!
+//! With `--watch` the atlas is re-imported each time the sources are
+//! saved, so the game hot-reloads it.
+use image::{io::Reader as ImageReader, RgbaImage};
+use serde::{Deserialize, Serialize};
+use serde_json::ser::PrettyFormatter;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+mod aseprite;
+mod packer;
+
+const SPRITE_LAYER: &str = "sprite";
+const MASK_LAYER_PREFIX: &str = "mask_";
+const EVENTS_SEPARATOR: char = ',';
+
+/// Sprites are extruded on the sheet by repeating their border pixels,
+/// so the linear filtering doesn't bleed the neighbor sprites in.
+const EXTRUSION: u32 = 1;
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(300);
+
+type Result<T> = std::result::Result<T, String>;
+
+struct Sprite {
+    name: String,
+    frame_idx: usize,
+    image: RgbaImage,
+    duration: f32,
+    events: Vec<String>,
+    masks: BTreeMap<String, Mask>,
+    insets: Option<Insets>,
+}
+
+/// Mask rect in the sprite image coordinates (y grows down).
+#[derive(Copy, Clone)]
+struct Mask {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+/// Nine-patch slice key, it's valid from its frame until the next key.
+/// The center is relative to the slice origin, all coordinates are in
+/// the sprite image (y grows down).
+struct SliceKey {
+    frame_idx: usize,
+    x: i32,
+    y: i32,
+    center_x: i32,
+    center_y: i32,
+    center_w: u32,
+    center_h: u32,
+}
+
+#[derive(Copy, Clone, Serialize)]
+struct Insets {
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+}
+
+#[derive(Deserialize)]
+struct SheetMeta {
+    frames: Vec<SheetFrame>,
+    meta: SheetInfo,
+}
+
+#[derive(Deserialize)]
+struct SheetFrame {
+    filename: String,
+    frame: SheetRect,
+    duration: u32,
+}
+
+#[derive(Deserialize)]
+struct SheetRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct SheetInfo {
+    image: String,
+    layers: Vec<SheetLayer>,
+    #[serde(default)]
+    slices: Vec<SheetSlice>,
+}
+
+#[derive(Deserialize)]
+struct SheetLayer {
+    name: String,
+    #[serde(default)]
+    cels: Vec<SheetCel>,
+}
+
+#[derive(Deserialize)]
+struct SheetCel {
+    frame: usize,
+    #[serde(default)]
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct SheetSlice {
+    keys: Vec<SheetSliceKey>,
+}
+
+#[derive(Deserialize)]
+struct SheetSliceKey {
+    frame: usize,
+    bounds: SheetRect,
+    center: Option<SheetRect>,
+}
+
+#[derive(Serialize)]
+struct AtlasMeta {
+    size: [u32; 2],
+    frames: BTreeMap<String, Vec<FrameMeta>>,
+}
+
+#[derive(Serialize)]
+struct FrameMeta {
+    sprite: RectMeta,
+    masks: BTreeMap<String, RectMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hitboxes: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    events: Vec<String>,
+    duration: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    insets: Option<Insets>,
+}
+
+#[derive(Serialize)]
+struct RectMeta {
+    name: String,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    frame_idx: usize,
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let is_watch = args.iter().any(|arg| arg == "--watch");
+    args.retain(|arg| arg != "--watch");
+    let aseprite_dir = args.first().map_or("./aseprite", |s| s.as_str());
+    let out_dir = args.get(1).map_or("./assets/sprites", |s| s.as_str());
+
+    if is_watch {
+        watch(aseprite_dir, out_dir);
+    } else if let Err(message) = import(aseprite_dir, out_dir) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+/// Errors are only printed, so the broken file can be fixed and saved
+/// again.
+fn watch(aseprite_dir: &str, out_dir: &str) -> ! {
+    let mut prev_mtimes = None;
+    loop {
+        let mtimes = get_mtimes(aseprite_dir);
+        if prev_mtimes.as_ref() != Some(&mtimes) {
+            if let Err(message) = import(aseprite_dir, out_dir) {
+                eprintln!("{}", message);
+            }
+            prev_mtimes = Some(mtimes);
+        }
+
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+fn get_mtimes(dir: &str) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut mtimes: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let mtime = entry.metadata().and_then(|meta| meta.modified());
+            (entry.path(), mtime.ok())
+        })
+        .collect();
+    mtimes.sort();
+
+    mtimes
+}
+
+fn import(aseprite_dir: &str, out_dir: &str) -> Result<()> {
+    let mut fps: Vec<_> = fs::read_dir(aseprite_dir)
+        .map_err(|e| format!("Can't read {}: {}", aseprite_dir, e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    fps.sort();
+
+    let mut sprites = Vec::new();
+    for fp in fps {
+        let ext = fp.extension().and_then(|ext| ext.to_str());
+        match ext {
+            Some("json") => sprites.extend(load_sheet(&fp)?),
+            Some("aseprite" | "ase") => {
+                sprites.extend(aseprite::load_aseprite(&fp)?)
+            }
+            _ => {}
+        }
+    }
+
+    if sprites.is_empty() {
+        return Err(format!("No sprites found in {}", aseprite_dir));
+    }
+
+    let meta_fp = Path::new(out_dir).join("atlas.json");
+    let image_fp = Path::new(out_dir).join("atlas.png");
+    let hitboxes = load_hitboxes(&meta_fp);
+    let (meta, image) = pack_atlas(sprites, &hitboxes)?;
+
+    let mut json = Vec::new();
+    let formatter = PrettyFormatter::with_indent(b"    ");
+    let mut serializer =
+        serde_json::Serializer::with_formatter(&mut json, formatter);
+    meta.serialize(&mut serializer).unwrap();
+    fs::write(&meta_fp, json).map_err(|e| {
+        format!("Can't write {}: {}", meta_fp.display(), e)
+    })?;
+    image.save(&image_fp).map_err(|e| {
+        format!("Can't write {}: {}", image_fp.display(), e)
+    })?;
+
+    println!(
+        "Packed {} frames into {}x{} atlas",
+        meta.frames
+            .values()
+            .map(|frames| frames.len())
+            .sum::<usize>(),
+        meta.size[0],
+        meta.size[1],
+    );
+
+    Ok(())
+}
+
+/// Reads the sprites of the single Aseprite export (json meta with its
+/// sheet image).
+fn load_sheet(meta_fp: &Path) -> Result<Vec<Sprite>> {
+    let meta = fs::read_to_string(meta_fp)
+        .map_err(|e| format!("Can't read {}: {}", meta_fp.display(), e))?;
+    let meta: SheetMeta = serde_json::from_str(&meta).map_err(|e| {
+        format!("Can't parse {}: {}", meta_fp.display(), e)
+    })?;
+
+    let sheet_fp = meta_fp.with_file_name(&meta.meta.image);
+    let sheet = ImageReader::open(&sheet_fp)
+        .map_err(|e| format!("Can't read {}: {}", sheet_fp.display(), e))?
+        .decode()
+        .map_err(|e| {
+            format!("Can't load image {}: {}", sheet_fp.display(), e)
+        })?
+        .to_rgba8();
+
+    // Frame events are stored in the user data of the sprite layer
+    // cels, e.g. "combo_window_open,footstep"
+    let sprite_layer = meta
+        .meta
+        .layers
+        .iter()
+        .find(|layer| layer.name == SPRITE_LAYER)
+        .ok_or_else(|| {
+            format!("{} is missing the `sprite` layer", meta_fp.display())
+        })?;
+    let frame_events: HashMap<usize, Vec<String>> = sprite_layer
+        .cels
+        .iter()
+        .map(|cel| (cel.frame, parse_events(&cel.data)))
+        .collect();
+
+    let slice_keys: Vec<SliceKey> = meta
+        .meta
+        .slices
+        .iter()
+        .find(|slice| slice.keys.iter().any(|key| key.center.is_some()))
+        .map(|slice| {
+            slice
+                .keys
+                .iter()
+                .filter_map(|key| {
+                    let center = key.center.as_ref()?;
+                    Some(SliceKey {
+                        frame_idx: key.frame,
+                        x: key.bounds.x as i32,
+                        y: key.bounds.y as i32,
+                        center_x: center.x as i32,
+                        center_y: center.y as i32,
+                        center_w: center.w,
+                        center_h: center.h,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut sprites: BTreeMap<(String, usize), Sprite> = BTreeMap::new();
+    let mut masks = Vec::new();
+    for frame in &meta.frames {
+        let parts: Vec<&str> = frame.filename.split('.').collect();
+        let [sprite_name, layer_name, tag, frame_idx] = parts[..] else {
+            return Err(format!(
+                "{}: frame name {} should be {{title}}.{{layer}}.{{tag}}.{{frame}}",
+                meta_fp.display(),
+                frame.filename
+            ));
+        };
+        let name = if tag.is_empty() {
+            sprite_name.to_string()
+        } else {
+            format!("{}_{}", sprite_name, tag)
+        };
+        let frame_idx: usize = frame_idx.parse().map_err(|_| {
+            format!(
+                "{}: frame name {} has invalid frame index",
+                meta_fp.display(),
+                frame.filename
+            )
+        })?;
+
+        let SheetRect { x, y, w, h } = frame.frame;
+        let image =
+            image::imageops::crop_imm(&sheet, x, y, w, h).to_image();
+        if layer_name == SPRITE_LAYER {
+            let sprite = Sprite {
+                name: name.clone(),
+                frame_idx,
+                image,
+                // Aseprite frame durations are in milliseconds
+                duration: frame.duration as f32 / 1000.0,
+                events: frame_events
+                    .get(&frame_idx)
+                    .cloned()
+                    .unwrap_or_default(),
+                masks: BTreeMap::new(),
+                insets: get_insets(&slice_keys, frame_idx, w, h),
+            };
+            sprites.insert((name, frame_idx), sprite);
+        } else if let Some(mask_name) =
+            layer_name.strip_prefix(MASK_LAYER_PREFIX)
+        {
+            if let Some(mask) = get_mask(&image) {
+                masks.push((name, frame_idx, mask_name.to_string(), mask));
+            }
+        } else {
+            return Err(format!(
+                "{}: layer name {} is not valid. The layer name should \
+                 be `sprite` (which represents the actual sprite) or \
+                 start with `mask_`",
+                meta_fp.display(),
+                layer_name
+            ));
+        }
+    }
+
+    for (name, frame_idx, mask_name, mask) in masks {
+        if let Some(sprite) = sprites.get_mut(&(name, frame_idx)) {
+            sprite.masks.insert(mask_name, mask);
+        }
+    }
+
+    Ok(sprites.into_values().collect())
+}
+
+fn parse_events(data: &str) -> Vec<String> {
+    data.split(EVENTS_SEPARATOR)
+        .map(|event| event.trim())
+        .filter(|event| !event.is_empty())
+        .map(|event| event.to_string())
+        .collect()
+}
+
+/// Insets of the key, which is valid on the frame, clamped to the
+/// sprite image of size (w, h).
+fn get_insets(
+    keys: &[SliceKey],
+    frame_idx: usize,
+    w: u32,
+    h: u32,
+) -> Option<Insets> {
+    let key = keys
+        .iter()
+        .filter(|key| key.frame_idx <= frame_idx)
+        .max_by_key(|key| key.frame_idx)?;
+    let clamp = |v: i64, max: u32| v.clamp(0, max as i64) as u32;
+    let left = key.x as i64 + key.center_x as i64;
+    let top = key.y as i64 + key.center_y as i64;
+
+    Some(Insets {
+        left: clamp(left, w),
+        right: w - clamp(left + key.center_w as i64, w),
+        top: clamp(top, h),
+        bottom: h - clamp(top + key.center_h as i64, h),
+    })
+}
+
+/// Bounding rect of the non-transparent pixels.
+fn get_mask(image: &RgbaImage) -> Option<Mask> {
+    let mut min = (u32::MAX, u32::MAX);
+    let mut max = (0, 0);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] > 0 {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+    }
+
+    (min.0 <= max.0).then(|| Mask {
+        x: min.0,
+        y: min.1,
+        w: max.0 - min.0 + 1,
+        h: max.1 - min.1 + 1,
+    })
+}
+
+/// Hand-authored hitbox data of the previous atlas, so it survives the
+/// re-import.
+fn load_hitboxes(
+    meta_fp: &Path,
+) -> HashMap<(String, usize), serde_json::Value> {
+    let mut hitboxes = HashMap::new();
+    let Some(meta) = fs::read_to_string(meta_fp).ok().and_then(|meta| {
+        serde_json::from_str::<serde_json::Value>(&meta).ok()
+    }) else {
+        return hitboxes;
+    };
+
+    let frames = meta["frames"].as_object().into_iter().flatten();
+    for (name, frames) in frames {
+        for frame in frames.as_array().into_iter().flatten() {
+            let frame_idx = frame["sprite"]["frame_idx"].as_u64();
+            if let (Some(frame_idx), Some(data)) =
+                (frame_idx, frame.get("hitboxes"))
+            {
+                hitboxes.insert(
+                    (name.clone(), frame_idx as usize),
+                    data.clone(),
+                );
+            }
+        }
+    }
+
+    hitboxes
+}
+
+fn pack_atlas(
+    sprites: Vec<Sprite>,
+    hitboxes: &HashMap<(String, usize), serde_json::Value>,
+) -> Result<(AtlasMeta, RgbaImage)> {
+    let sizes: Vec<(u32, u32)> = sprites
+        .iter()
+        .map(|sprite| {
+            let (w, h) = sprite.image.dimensions();
+            (w + 2 * EXTRUSION, h + 2 * EXTRUSION)
+        })
+        .collect();
+    let ((sheet_w, sheet_h), positions) = packer::pack(&sizes)?;
+
+    let mut sheet = RgbaImage::new(sheet_w, sheet_h);
+    let mut frames: BTreeMap<String, Vec<FrameMeta>> = BTreeMap::new();
+    for (sprite, (x, y)) in sprites.into_iter().zip(positions) {
+        blit_extruded(&mut sheet, &sprite.image, x, y);
+
+        // Aseprite assumes that the min y is at the top of the sheet,
+        // but the meta y is flipped and the 0th y is at the bottom, as
+        // in the OpenGL texture coordinates
+        let (w, h) = sprite.image.dimensions();
+        let x = x + EXTRUSION;
+        let y = sheet_h - (y + EXTRUSION) - 1;
+        let rect_meta = |x, y, w, h| RectMeta {
+            name: sprite.name.clone(),
+            x,
+            y,
+            w,
+            h,
+            frame_idx: sprite.frame_idx,
+        };
+
+        // Mask y is its top relative to the sprite bottom
+        let masks = sprite
+            .masks
+            .iter()
+            .map(|(name, m)| {
+                (name.clone(), rect_meta(m.x, h - m.y, m.w, m.h))
+            })
+            .collect();
+
+        let frame = FrameMeta {
+            sprite: rect_meta(x, y, w, h),
+            masks,
+            hitboxes: hitboxes
+                .get(&(sprite.name.clone(), sprite.frame_idx))
+                .cloned(),
+            events: sprite.events,
+            duration: sprite.duration,
+            insets: sprite.insets,
+        };
+        frames.entry(sprite.name).or_default().push(frame);
+    }
+
+    let meta = AtlasMeta {
+        size: [sheet_w, sheet_h],
+        frames,
+    };
+
+    Ok((meta, sheet))
+}
+
+fn blit_extruded(
+    sheet: &mut RgbaImage,
+    image: &RgbaImage,
+    x: u32,
+    y: u32,
+) {
+    let (w, h) = image.dimensions();
+    for dy in 0..h + 2 * EXTRUSION {
+        for dx in 0..w + 2 * EXTRUSION {
+            let src_x = dx.saturating_sub(EXTRUSION).min(w - 1);
+            let src_y = dy.saturating_sub(EXTRUSION).min(h - 1);
+            let pixel = *image.get_pixel(src_x, src_y);
+            sheet.put_pixel(x + dx, y + dy, pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(frame_idx: usize, x: i32, center_w: u32) -> SliceKey {
+        SliceKey {
+            frame_idx,
+            x,
+            y: 0,
+            center_x: 2,
+            center_y: 3,
+            center_w,
+            center_h: 4,
+        }
+    }
+
+    fn get_ltrb(insets: Option<Insets>) -> Option<[u32; 4]> {
+        insets.map(|i| [i.left, i.top, i.right, i.bottom])
+    }
+
+    #[test]
+    fn test_get_insets_uses_key_of_frame() {
+        let keys = [key(1, 0, 4), key(3, 1, 2)];
+
+        assert_eq!(get_ltrb(get_insets(&keys, 0, 10, 10)), None);
+        assert_eq!(
+            get_ltrb(get_insets(&keys, 1, 10, 10)),
+            Some([2, 3, 4, 3])
+        );
+        assert_eq!(
+            get_ltrb(get_insets(&keys, 2, 10, 10)),
+            Some([2, 3, 4, 3])
+        );
+        assert_eq!(
+            get_ltrb(get_insets(&keys, 3, 10, 10)),
+            Some([3, 3, 5, 3])
+        );
+        assert_eq!(
+            get_ltrb(get_insets(&keys, 9, 10, 10)),
+            Some([3, 3, 5, 3])
+        );
+    }
+
+    #[test]
+    fn test_get_insets_clamps_to_image() {
+        // The center starts left of the image and ends right of it
+        let keys = [key(0, -5, 20)];
+        assert_eq!(
+            get_ltrb(get_insets(&keys, 0, 8, 10)),
+            Some([0, 3, 0, 3])
+        );
+
+        // The center is out of the image
+        let keys = [key(0, 10, 4)];
+        assert_eq!(
+            get_ltrb(get_insets(&keys, 0, 8, 5)),
+            Some([8, 3, 0, 0])
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::Result;
+
+/// Bottom-left skyline packer. The skyline is the list of the top
+/// edges of the already packed rects (y grows down), so each new rect
+/// is placed on the lowest segment where it fits.
+pub struct SkylinePacker {
+    width: u32,
+    height: u32,
+    skyline: Vec<Segment>,
+}
+
+#[derive(Copy, Clone)]
+struct Segment {
+    x: u32,
+    y: u32,
+    w: u32,
+}
+
+impl SkylinePacker {
+    pub fn new(width: u32) -> Self {
+        Self {
+            width,
+            height: 0,
+            skyline: vec![Segment {
+                x: 0,
+                y: 0,
+                w: width,
+            }],
+        }
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    /// Top left corner of the packed rect or None if it's wider than
+    /// the packer.
+    pub fn pack(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        let mut best: Option<(usize, u32)> = None;
+        for idx in 0..self.skyline.len() {
+            let Some(y) = self.get_fit_y(idx, w) else {
+                continue;
+            };
+
+            if best.is_none_or(|(_, best_y)| y < best_y) {
+                best = Some((idx, y));
+            }
+        }
+
+        let (idx, y) = best?;
+        let x = self.skyline[idx].x;
+        self.insert_segment(idx, Segment { x, y: y + h, w });
+        self.height = self.height.max(y + h);
+
+        Some((x, y))
+    }
+
+    /// The y at which the rect, started at the segment, lies on top of
+    /// all segments under it.
+    fn get_fit_y(&self, idx: usize, w: u32) -> Option<u32> {
+        let x = self.skyline[idx].x;
+        if x + w > self.width {
+            return None;
+        }
+
+        let mut y = 0;
+        let mut covered_w = 0;
+        for segment in &self.skyline[idx..] {
+            if covered_w >= w {
+                break;
+            }
+            y = y.max(segment.y);
+            covered_w += segment.w;
+        }
+
+        Some(y)
+    }
+
+    fn insert_segment(&mut self, idx: usize, segment: Segment) {
+        self.skyline.insert(idx, segment);
+
+        // Cut the segments shadowed by the new one
+        let right_x = segment.x + segment.w;
+        while idx + 1 < self.skyline.len() {
+            let next = &mut self.skyline[idx + 1];
+            if next.x >= right_x {
+                break;
+            }
+
+            let shadowed_w = right_x - next.x;
+            if shadowed_w < next.w {
+                next.x += shadowed_w;
+                next.w -= shadowed_w;
+                break;
+            }
+            self.skyline.remove(idx + 1);
+        }
+
+        // Merge the neighbor segments with the same height
+        let mut i = 0;
+        while i + 1 < self.skyline.len() {
+            if self.skyline[i].y == self.skyline[i + 1].y {
+                self.skyline[i].w += self.skyline[i + 1].w;
+                self.skyline.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Sheet size and the top left corners of the packed rects.
+pub type Packing = ((u32, u32), Vec<(u32, u32)>);
+
+/// Packs the rects (w, h) into the sheet of the roughly square shape.
+/// The corners are in the same order as the rects were given. Nothing
+/// to pack is an error, the sheet would be empty.
+pub fn pack(sizes: &[(u32, u32)]) -> Result<Packing> {
+    if sizes.is_empty() {
+        return Err("No rects to pack".to_string());
+    }
+
+    let max_w = sizes.iter().map(|(w, _)| *w).max().unwrap_or(0);
+    let area: u32 = sizes.iter().map(|(w, h)| w * h).sum();
+    let width = max_w.max((area as f32).sqrt().ceil() as u32);
+
+    // Higher rects go first, so the skyline stays flat
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| (u32::MAX - sizes[i].1, u32::MAX - sizes[i].0));
+
+    let mut packer = SkylinePacker::new(width);
+    let mut positions = vec![(0, 0); sizes.len()];
+    for i in order {
+        let (w, h) = sizes[i];
+        positions[i] = packer
+            .pack(w, h)
+            .expect("Packer should be wider than the widest rect");
+    }
+
+    Ok(((width, packer.get_height()), positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EXTRUSION;
+
+    /// Packs the rects and checks that they are inside the sheet and
+    /// don't overlap each other.
+    fn assert_valid_packing(sizes: &[(u32, u32)]) -> (u32, u32) {
+        let ((sheet_w, sheet_h), positions) = pack(sizes).unwrap();
+        assert_eq!(positions.len(), sizes.len());
+
+        let rects: Vec<_> = positions
+            .iter()
+            .zip(sizes)
+            .map(|(&(x, y), &(w, h))| (x, y, w, h))
+            .collect();
+        for (i, &(x, y, w, h)) in rects.iter().enumerate() {
+            assert!(
+                x + w <= sheet_w && y + h <= sheet_h,
+                "{:?} is outside of the {}x{} sheet",
+                rects[i],
+                sheet_w,
+                sheet_h
+            );
+
+            for &(other_x, other_y, other_w, other_h) in &rects[i + 1..] {
+                let is_overlap = x < other_x + other_w
+                    && other_x < x + w
+                    && y < other_y + other_h
+                    && other_y < y + h;
+                assert!(!is_overlap, "{:?} overlaps other rect", rects[i]);
+            }
+        }
+
+        (sheet_w, sheet_h)
+    }
+
+    #[test]
+    fn test_pack_single_rect() {
+        // The sheet is as wide as the square of the same area
+        assert_eq!(pack(&[(5, 7)]), Ok(((6, 7), vec![(0, 0)])));
+        assert_eq!(pack(&[(9, 2)]), Ok(((9, 2), vec![(0, 0)])));
+    }
+
+    #[test]
+    fn test_pack_nothing() {
+        assert!(pack(&[]).is_err());
+    }
+
+    #[test]
+    fn test_pack_same_rects() {
+        let (sheet_w, sheet_h) = assert_valid_packing(&[(4, 4); 16]);
+
+        assert_eq!((sheet_w, sheet_h), (16, 16));
+    }
+
+    #[test]
+    fn test_pack_many_mixed_extruded_rects() {
+        // Deterministic pseudo-random sizes
+        let mut seed: u32 = 7;
+        let mut next = |max: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            1 + (seed >> 16) % max
+        };
+        let sizes: Vec<_> = (0..200)
+            .map(|i| {
+                let (w, h) = if i % 10 == 0 {
+                    (next(96), next(8))
+                } else {
+                    (next(32), next(48))
+                };
+                (w + 2 * EXTRUSION, h + 2 * EXTRUSION)
+            })
+            .collect();
+
+        let (sheet_w, sheet_h) = assert_valid_packing(&sizes);
+        let area: u32 = sizes.iter().map(|(w, h)| w * h).sum();
+        assert!(sheet_w * sheet_h < 2 * area, "The packing is too loose");
+    }
+}
@@ -0,0 +1,243 @@
+//! Immediate-mode debug shapes. They are queued at any point of the
+//! frame and drawn on top of everything else by `flush`. The shapes
+//! are queued only in the debug builds or with the `debug_draw`
+//! feature, otherwise the calls are compiled out.
+use crate::renderer::*;
+use crate::vec::*;
+use std::f32::consts::PI;
+
+const WORLD_LINE_WIDTH: f32 = 0.5;
+const SCREEN_LINE_WIDTH: f32 = 2.0;
+const WORLD_FONT_SIZE: f32 = 8.0;
+const SCREEN_FONT_SIZE: f32 = 16.0;
+const ARROW_HEAD_ANGLE: f32 = 0.15 * PI;
+const N_CIRCLE_SEGMENTS: usize = 24;
+
+struct DebugLine {
+    start: Vec2<f32>,
+    end: Vec2<f32>,
+    color: Color,
+    space: SpaceType,
+}
+
+struct DebugText {
+    text: String,
+    position: Vec2<f32>,
+    color: Color,
+    space: SpaceType,
+}
+
+pub struct DebugDraw {
+    lines: Vec<DebugLine>,
+    texts: Vec<DebugText>,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+            texts: Vec::new(),
+        }
+    }
+
+    pub fn line(
+        &mut self,
+        start: Vec2<f32>,
+        end: Vec2<f32>,
+        color: Color,
+        space: SpaceType,
+    ) {
+        if cfg!(any(debug_assertions, feature = "debug_draw")) {
+            self.lines.push(DebugLine {
+                start,
+                end,
+                color,
+                space,
+            });
+        }
+    }
+
+    /// Line with the head at the end, the head size is relative to the
+    /// line length.
+    pub fn arrow(
+        &mut self,
+        start: Vec2<f32>,
+        end: Vec2<f32>,
+        color: Color,
+        space: SpaceType,
+    ) {
+        if !cfg!(any(debug_assertions, feature = "debug_draw")) {
+            return;
+        }
+
+        self.line(start, end, color, space);
+        let head = (start - end).scale(0.25);
+        for angle in [ARROW_HEAD_ANGLE, -ARROW_HEAD_ANGLE] {
+            let head_end = (end + head).rotate(end, angle);
+            self.line(end, head_end, color, space);
+        }
+    }
+
+    pub fn circle(
+        &mut self,
+        center: Vec2<f32>,
+        radius: f32,
+        color: Color,
+        space: SpaceType,
+    ) {
+        if !cfg!(any(debug_assertions, feature = "debug_draw")) {
+            return;
+        }
+
+        let step = 2.0 * PI / N_CIRCLE_SEGMENTS as f32;
+        for i in 0..N_CIRCLE_SEGMENTS {
+            let start = Vec2::from_angle(step * i as f32).scale(radius);
+            let end =
+                Vec2::from_angle(step * (i + 1) as f32).scale(radius);
+            self.line(center + start, center + end, color, space);
+        }
+    }
+
+    /// Outline of the rect.
+    pub fn rect(&mut self, rect: Rect, color: Color, space: SpaceType) {
+        let corners = [
+            rect.get_bot_left(),
+            rect.get_bot_right(),
+            rect.get_top_right(),
+            rect.get_top_left(),
+        ];
+        self.polygon(&corners, color, space);
+    }
+
+    /// Closed outline through the points, the last point is connected
+    /// to the first one.
+    pub fn polygon(
+        &mut self,
+        points: &[Vec2<f32>],
+        color: Color,
+        space: SpaceType,
+    ) {
+        if !cfg!(any(debug_assertions, feature = "debug_draw")) {
+            return;
+        }
+
+        for (i, start) in points.iter().enumerate() {
+            let end = points[(i + 1) % points.len()];
+            self.line(*start, end, color, space);
+        }
+    }
+
+    /// Text label, centered above the position.
+    pub fn text(
+        &mut self,
+        text: &str,
+        position: Vec2<f32>,
+        color: Color,
+        space: SpaceType,
+    ) {
+        if cfg!(any(debug_assertions, feature = "debug_draw")) {
+            self.texts.push(DebugText {
+                text: text.to_string(),
+                position,
+                color,
+                space,
+            });
+        }
+    }
+
+    /// Pushes the queued shapes to the renderer and clears the queue.
+    pub fn flush(&mut self, renderer: &mut Renderer) {
+        for line in self.lines.drain(..) {
+            let width = match line.space {
+                SpaceType::ScreenSpace => SCREEN_LINE_WIDTH,
+                _ => WORLD_LINE_WIDTH,
+            };
+            let diff = line.end - line.start;
+            let center = line.start + diff.scale(0.5);
+            let rect =
+                Rect::from_center(center, Vec2::new(diff.len(), width));
+
+            let mut primitive =
+                DrawPrimitive::world_rect(rect, line.color);
+            primitive.space = line.space;
+            primitive.layer = RenderLayer::Debug;
+            primitive.transform = Transform::from_pivot(
+                center,
+                diff.y.atan2(diff.x),
+                Vec2::new(1.0, 1.0),
+            );
+            renderer.push_primitive(primitive);
+        }
+
+        for text in self.texts.drain(..) {
+            let font_size = match text.space {
+                SpaceType::ScreenSpace => SCREEN_FONT_SIZE,
+                _ => WORLD_FONT_SIZE,
+            };
+            renderer.push_text(
+                &text.text,
+                Pivot::BotCenter(text.position),
+                text.space,
+                RenderLayer::Debug,
+                font_size,
+                text.color,
+            );
+        }
+    }
+}
+
+#[cfg(all(test, any(debug_assertions, feature = "debug_draw")))]
+mod tests {
+    use super::*;
+
+    fn get_segments(debug_draw: &DebugDraw) -> Vec<[f32; 4]> {
+        debug_draw
+            .lines
+            .iter()
+            .map(|line| {
+                [line.start.x, line.start.y, line.end.x, line.end.y]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_polygon_is_closed() {
+        let mut debug_draw = DebugDraw::new();
+        let points = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(1.0, 3.0),
+        ];
+        debug_draw.polygon(
+            &points,
+            Color::red(1.0),
+            SpaceType::WorldSpace,
+        );
+
+        assert_eq!(
+            get_segments(&debug_draw),
+            [
+                [0.0, 0.0, 2.0, 0.0],
+                [2.0, 0.0, 1.0, 3.0],
+                [1.0, 3.0, 0.0, 0.0]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rect_is_polygon_of_corners() {
+        let mut debug_draw = DebugDraw::new();
+        let rect = Rect::from_bot_left(Vec2::zeros(), Vec2::new(2.0, 1.0));
+        debug_draw.rect(rect, Color::red(1.0), SpaceType::WorldSpace);
+
+        assert_eq!(
+            get_segments(&debug_draw),
+            [
+                [0.0, 0.0, 2.0, 0.0],
+                [2.0, 0.0, 2.0, 1.0],
+                [2.0, 1.0, 0.0, 1.0],
+                [0.0, 1.0, 0.0, 0.0]
+            ]
+        );
+    }
+}
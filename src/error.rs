@@ -0,0 +1,124 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        fp: String,
+        source: io::Error,
+    },
+    Json {
+        fp: String,
+        source: serde_json::Error,
+    },
+    Image {
+        fp: String,
+        source: image::ImageError,
+    },
+    ImageSave {
+        fp: String,
+        source: image::ImageError,
+    },
+    Font {
+        fp: String,
+        message: &'static str,
+    },
+    MissingAnimation {
+        fp: String,
+        name: String,
+    },
+    UnloadedAtlas,
+    EmptyTexture,
+    Shader {
+        fp: String,
+        log: String,
+    },
+    Link {
+        fps: Vec<String>,
+        log: String,
+    },
+    Gl(String),
+    Sdl(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(fp: &str) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| Error::Io {
+            fp: fp.to_string(),
+            source,
+        }
+    }
+
+    pub fn json(fp: &str) -> impl FnOnce(serde_json::Error) -> Self + '_ {
+        move |source| Error::Json {
+            fp: fp.to_string(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+
+        match self {
+            Io { fp, source } => {
+                write!(f, "Can't read {}: {}", fp, source)
+            }
+            Json { fp, source } => {
+                write!(f, "Can't parse {}: {}", fp, source)
+            }
+            Image { fp, source } => {
+                write!(f, "Can't load image {}: {}", fp, source)
+            }
+            ImageSave { fp, source } => {
+                write!(f, "Can't save image {}: {}", fp, source)
+            }
+            Font { fp, message } => {
+                write!(f, "Can't load font {}: {}", fp, message)
+            }
+            MissingAnimation { fp, name } => {
+                write!(
+                    f,
+                    "Frame atlas {} doesn't have animation: {}",
+                    fp, name
+                )
+            }
+            UnloadedAtlas => {
+                write!(f, "Frame atlas is unloaded, its handle is stale")
+            }
+            EmptyTexture => {
+                write!(f, "Can't create texture from the empty image")
+            }
+            Shader { fp, log } => {
+                write!(f, "Can't compile shader {}:\n{}", fp, log)
+            }
+            Link { fps, log } => {
+                write!(
+                    f,
+                    "Can't link program {}:\n{}",
+                    fps.join(", "),
+                    log
+                )
+            }
+            Gl(message) => write!(f, "OpenGL error: {}", message),
+            Sdl(message) => write!(f, "SDL error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use Error::*;
+
+        match self {
+            Io { source, .. } => Some(source),
+            Json { source, .. } => Some(source),
+            Image { source, .. } => Some(source),
+            ImageSave { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -1,8 +1,12 @@
+use crate::assets::AtlasHandle;
+use crate::error::{Error, Result};
 use crate::vec::*;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
+
+const HITBOX_MASK_PREFIX: &str = "attack";
+const HURTBOX_MASK_PREFIX: &str = "hurt";
 
 #[derive(Deserialize, Copy, Clone, Debug)]
 pub struct XYWH {
@@ -35,144 +39,1006 @@ impl XYWH {
     }
 }
 
+/// Per-hitbox attack data. The damage is relative to the attacker base
+/// damage and the knockback is given for the non-flipped sprite.
+/// Global hit-stop freezes the whole world instead of only the attacker
+/// and the target. Without the re-hit interval an entity is hit only
+/// once per animation, and non-piercing hitboxes hit only one entity.
+#[derive(Deserialize, Copy, Clone, Debug)]
+#[serde(default)]
+pub struct HitboxData {
+    pub damage_scale: f32,
+    pub knockback: [f32; 2],
+    pub hit_stop: f32,
+    pub is_global_hit_stop: bool,
+    pub rehit_interval: Option<f32>,
+    pub is_piercing: bool,
+}
+
+impl Default for HitboxData {
+    fn default() -> Self {
+        Self {
+            damage_scale: 1.0,
+            knockback: [0.0, 0.0],
+            hit_stop: 0.06,
+            is_global_hit_stop: false,
+            rehit_interval: None,
+            is_piercing: true,
+        }
+    }
+}
+
+/// Nine-slice borders of the sprite in pixels. The corners keep their
+/// size, the edges and the center are stretched.
+#[derive(Deserialize, Copy, Clone, Default, Debug)]
+pub struct Insets {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct Frame {
     pub sprite: XYWH,
     masks: HashMap<String, XYWH>,
+    #[serde(default)]
+    hitboxes: HashMap<String, HitboxData>,
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Frame duration in seconds, overrides the animation one
+    #[serde(default)]
+    pub duration: Option<f32>,
+    /// Set for the sprites with the Aseprite nine-patch slice
+    #[serde(default)]
+    pub insets: Option<Insets>,
+}
+
+/// Transformation of the sprite, which the frame masks follow. The
+/// rotation is counter-clockwise in radians around the sprite center,
+/// rotated masks are replaced by their bounding boxes.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct FrameTransform {
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub rotation: f32,
+}
+
+impl FrameTransform {
+    pub fn from_flip_x(flip_x: bool) -> Self {
+        Self {
+            flip_x,
+            ..Default::default()
+        }
+    }
 }
 
 impl Frame {
+    /// Masks named `attack` or `attack_*` with their hitbox data.
+    pub fn get_hitboxes(
+        &self,
+        pivot: Pivot,
+        transform: FrameTransform,
+    ) -> Vec<(Rect, HitboxData)> {
+        self.get_mask_names(HITBOX_MASK_PREFIX)
+            .into_iter()
+            .map(|name| {
+                let rect = self.get_mask(name, pivot, transform).unwrap();
+                let mut data =
+                    self.hitboxes.get(name).copied().unwrap_or_default();
+                let mut knockback =
+                    Vec2::new(data.knockback[0], data.knockback[1]);
+                if transform.flip_x {
+                    knockback.x *= -1.0;
+                }
+                if transform.flip_y {
+                    knockback.y *= -1.0;
+                }
+                knockback =
+                    knockback.rotate(Vec2::zeros(), transform.rotation);
+                data.knockback = knockback.to_array();
+
+                (rect, data)
+            })
+            .collect()
+    }
+
+    /// Masks named `hurt` or `hurt_*`.
+    pub fn get_hurtboxes(
+        &self,
+        pivot: Pivot,
+        transform: FrameTransform,
+    ) -> Vec<Rect> {
+        self.get_mask_names(HURTBOX_MASK_PREFIX)
+            .into_iter()
+            .map(|name| self.get_mask(name, pivot, transform).unwrap())
+            .collect()
+    }
+
+    fn get_mask_names(&self, prefix: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .masks
+            .keys()
+            .map(|name| name.as_str())
+            .filter(|name| {
+                *name == prefix
+                    || name
+                        .strip_prefix(prefix)
+                        .is_some_and(|suffix| suffix.starts_with('_'))
+            })
+            .collect();
+        names.sort();
+
+        names
+    }
+
+    /// The pivot is applied to the whole sprite, not to the mask.
     pub fn get_mask(
         &self,
         name: &str,
         pivot: Pivot,
-        flip: bool,
+        transform: FrameTransform,
     ) -> Option<Rect> {
-        use Pivot::*;
-
-        if let Some(xywh) = self.masks.get(name) {
-            let size = xywh.to_size();
-            let position = xywh.to_position();
-            let pivot = match pivot {
-                BotLeft(pivot) => pivot,
-                BotCenter(pivot) => {
-                    pivot.add_x(-0.5 * self.sprite.w as f32)
-                }
-                _ => {
-                    todo!()
-                }
-            };
+        let xywh = self.masks.get(name)?;
+        let sprite_size = self.sprite.to_size();
 
-            let rect = if flip {
-                let pivot = pivot.add_x(self.sprite.w as f32);
-                let position = pivot + position.mul_x(-1.0);
-                Rect::from_top_right(position, size)
-            } else {
-                let position = pivot + position;
-                Rect::from_top_left(position, size)
-            };
+        // Mask position is its top left corner relative to the sprite
+        // bottom left corner
+        let mask = Rect::from_top_left(xywh.to_position(), xywh.to_size());
+        let mut min = mask.get_bot_left();
+        let mut max = mask.get_top_right();
+        if transform.flip_x {
+            (min.x, max.x) =
+                (sprite_size.x - max.x, sprite_size.x - min.x);
+        }
+        if transform.flip_y {
+            (min.y, max.y) =
+                (sprite_size.y - max.y, sprite_size.y - min.y);
+        }
 
-            return Some(rect);
+        if transform.rotation != 0.0 {
+            let center = sprite_size.scale(0.5);
+            let corners = [
+                min,
+                max,
+                Vec2::new(min.x, max.y),
+                Vec2::new(max.x, min.y),
+            ]
+            .map(|corner| corner.rotate(center, transform.rotation));
+
+            min = corners[0];
+            max = corners[0];
+            for corner in corners {
+                min = Vec2::new(min.x.min(corner.x), min.y.min(corner.y));
+                max = Vec2::new(max.x.max(corner.x), max.y.max(corner.y));
+            }
         }
 
-        None
+        let sprite = Rect::from_pivot(pivot, sprite_size);
+        let rect = Rect::from_bot_left(min, max - min);
+
+        Some(rect.translate(sprite.get_bot_left()))
     }
 }
 
+/// Interned animation name, it's valid only for the atlas which has
+/// produced it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct AnimationId(usize);
+
+#[derive(Deserialize)]
+struct FrameAtlasMeta {
+    size: [u32; 2],
+    frames: HashMap<String, Vec<Frame>>,
+}
+
 #[derive(Deserialize, Clone)]
+#[serde(from = "FrameAtlasMeta")]
 pub struct FrameAtlas {
+    fp: String,
     size: [u32; 2],
-    #[serde(rename = "frames")]
-    name_to_frames: HashMap<String, Vec<Frame>>,
+    names: Vec<String>,
+    name_to_id: HashMap<String, AnimationId>,
+    frames: Vec<Vec<Frame>>,
+}
+
+impl From<FrameAtlasMeta> for FrameAtlas {
+    fn from(meta: FrameAtlasMeta) -> Self {
+        let mut name_to_frames: Vec<_> = meta.frames.into_iter().collect();
+        name_to_frames.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut names = Vec::with_capacity(name_to_frames.len());
+        let mut name_to_id = HashMap::with_capacity(name_to_frames.len());
+        let mut frames = Vec::with_capacity(name_to_frames.len());
+        for (idx, (name, animation_frames)) in
+            name_to_frames.into_iter().enumerate()
+        {
+            name_to_id.insert(name.clone(), AnimationId(idx));
+            names.push(name);
+            frames.push(animation_frames);
+        }
+
+        Self {
+            fp: String::new(),
+            size: meta.size,
+            names,
+            name_to_id,
+            frames,
+        }
+    }
 }
 
 impl FrameAtlas {
-    pub fn new(file_path: &str) -> Self {
-        let meta = fs::read_to_string(file_path).unwrap();
+    pub fn new(file_path: &str) -> Result<Self> {
+        let meta =
+            fs::read_to_string(file_path).map_err(Error::io(file_path))?;
 
-        serde_json::from_str(&meta).unwrap()
+        let mut atlas: Self =
+            serde_json::from_str(&meta).map_err(Error::json(file_path))?;
+        atlas.fp = file_path.to_string();
+
+        Ok(atlas)
+    }
+
+    pub fn get_animation_id(&self, name: &str) -> Result<AnimationId> {
+        self.name_to_id.get(name).copied().ok_or_else(|| {
+            Error::MissingAnimation {
+                fp: self.fp.clone(),
+                name: name.to_string(),
+            }
+        })
+    }
+
+    pub fn get_animation_name(&self, id: AnimationId) -> &str {
+        &self.names[id.0]
+    }
+
+    pub fn get_frames(&self, id: AnimationId) -> &[Frame] {
+        &self.frames[id.0]
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PlayMode {
+    Once,
+    Loop,
+    Reverse,
+    ReverseLoop,
+    PingPong,
+}
+
+impl PlayMode {
+    pub fn is_loop(self) -> bool {
+        use PlayMode::*;
+
+        matches!(self, Loop | ReverseLoop | PingPong)
+    }
+
+    /// Number of frames played in a single animation cycle. The
+    /// ping-pong cycle doesn't repeat the first and the last frames.
+    fn get_cycle_len(self, n_frames: usize) -> usize {
+        match self {
+            PlayMode::PingPong if n_frames > 1 => 2 * n_frames - 2,
+            _ => n_frames,
+        }
     }
 
-    pub fn new_animator(&'static self) -> FrameAnimator {
-        FrameAnimator::new(self)
+    fn get_frame_idx(self, cycle_idx: usize, n_frames: usize) -> usize {
+        use PlayMode::*;
+
+        match self {
+            Once | Loop => cycle_idx,
+            Reverse | ReverseLoop => n_frames - 1 - cycle_idx,
+            PingPong if cycle_idx < n_frames => cycle_idx,
+            PingPong => 2 * n_frames - 2 - cycle_idx,
+        }
     }
 }
 
+/// How `FrameAnimator::play_with_transition` switches from the current
+/// animation to the new one.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Transition {
+    /// Restart with the new animation right away
+    Immediate,
+    /// Switch after the current animation (or its loop cycle) ends
+    FinishCycle,
+    /// Switch right away, but keep the progress of the current animation
+    MatchFrame,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+struct Animation {
+    id: AnimationId,
+    frame_duration: f32,
+    mode: PlayMode,
+}
+
+const MAX_N_QUEUED_ANIMATIONS: usize = 4;
+
 #[derive(Copy, Clone)]
 pub struct FrameAnimator {
-    atlas: &'static FrameAtlas,
+    atlas: AtlasHandle,
     is_started: bool,
+    is_paused: bool,
+    // Progress of the previous animation to continue from on the next
+    // update, see `Transition::MatchFrame`
+    matched_progress: Option<f32>,
+    // Counts the started animations, so the id changes on each restart
+    // and stays the same across runs
     animation_id: u128,
 
-    name: &'static str,
-    frame_duration: f32,
-    is_repeat: bool,
+    animation: Animation,
+    queue: [Option<Animation>; MAX_N_QUEUED_ANIMATIONS],
+    time: f32,
 
+    // Cycle frames crossed during the last update, they may wrap around
+    // the cycle end
+    cycle_idx: Option<usize>,
+    first_crossed_cycle_idx: usize,
+    n_crossed_frames: usize,
+
+    /// Playback speed scale. Negative speed is treated as zero, the
+    /// reverse playback is done by `PlayMode::Reverse`.
+    pub speed: f32,
     pub progress: f32,
 }
 
 impl FrameAnimator {
-    pub fn new(atlas: &'static FrameAtlas) -> Self {
+    pub fn new(atlas: AtlasHandle) -> Self {
         Self {
             atlas,
             is_started: false,
+            is_paused: false,
+            matched_progress: None,
             animation_id: 0,
-            name: "",
-            frame_duration: 0.0,
-            is_repeat: false,
+            animation: Animation {
+                id: AnimationId(0),
+                frame_duration: 0.0,
+                mode: PlayMode::Once,
+            },
+            queue: [None; MAX_N_QUEUED_ANIMATIONS],
+            time: 0.0,
+            cycle_idx: None,
+            first_crossed_cycle_idx: 0,
+            n_crossed_frames: 0,
+            speed: 1.0,
             progress: 0.0,
         }
     }
 
+    /// The frame duration is used for the frames which don't have their
+    /// own duration in the atlas.
     pub fn play(
         &mut self,
-        name: &'static str,
+        id: AnimationId,
         frame_duration: f32,
-        is_repeat: bool,
+        mode: PlayMode,
     ) {
-        self.is_started = true;
+        self.play_with_transition(
+            id,
+            frame_duration,
+            mode,
+            Transition::Immediate,
+        );
+    }
 
-        if name != self.name
-            || frame_duration != self.frame_duration
-            || is_repeat != self.is_repeat
-        {
-            self.name = name;
-            self.frame_duration = frame_duration;
-            self.is_repeat = is_repeat;
-            self.progress = 0.0;
-            self.animation_id = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Clock may have gone backwards")
-                .as_nanos();
+    /// Playing the current animation again doesn't restart it, so it's
+    /// fine to call this on every update. The immediate transition still
+    /// drops the queued animations.
+    pub fn play_with_transition(
+        &mut self,
+        id: AnimationId,
+        frame_duration: f32,
+        mode: PlayMode,
+        transition: Transition,
+    ) {
+        use Transition::*;
+
+        let animation = Animation {
+            id,
+            frame_duration,
+            mode,
+        };
+        if self.is_started && animation == self.animation {
+            if matches!(transition, Immediate) {
+                self.clear_queue();
+            }
+            return;
+        }
+
+        if !self.is_started {
+            self.start(animation);
+            return;
         }
+
+        match transition {
+            Immediate => {
+                self.clear_queue();
+                self.start(animation);
+            }
+            FinishCycle => {
+                if self.queue[0] != Some(animation) {
+                    self.clear_queue();
+                    self.queue[0] = Some(animation);
+                }
+            }
+            MatchFrame => {
+                let progress = self.progress;
+                self.clear_queue();
+                self.start(animation);
+                self.matched_progress = Some(progress);
+            }
+        }
+    }
+
+    /// The queued animation starts when the current one (or its loop
+    /// cycle) and all the previously queued ones end. Returns `false` if
+    /// the queue is full and the animation is not queued.
+    pub fn queue(
+        &mut self,
+        id: AnimationId,
+        frame_duration: f32,
+        mode: PlayMode,
+    ) -> bool {
+        let animation = Animation {
+            id,
+            frame_duration,
+            mode,
+        };
+        if !self.is_started {
+            self.start(animation);
+            return true;
+        }
+
+        match self.queue.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(animation);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear_queue(&mut self) {
+        self.queue = [None; MAX_N_QUEUED_ANIMATIONS];
+    }
+
+    pub fn pause(&mut self) {
+        self.is_paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.is_paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    pub fn get_atlas_handle(&self) -> AtlasHandle {
+        self.atlas
+    }
+
+    /// Currently playing animation, it's `None` if nothing has been played
+    /// yet.
+    pub fn get_animation_id(&self) -> Option<AnimationId> {
+        self.is_started.then_some(self.animation.id)
     }
 
     pub fn is_finished(&self) -> bool {
-        !self.is_repeat && self.progress == 1.0
+        !self.animation.mode.is_loop()
+            && self.progress == 1.0
+            && self.queue[0].is_none()
+    }
+
+    /// Events of all the frames crossed during the last update, including
+    /// the skipped ones.
+    pub fn get_events<'a>(
+        &'a self,
+        atlas: &'a FrameAtlas,
+    ) -> impl Iterator<Item = &'a str> {
+        let frames = if self.n_crossed_frames > 0 {
+            atlas.get_frames(self.animation.id)
+        } else {
+            &[]
+        };
+        let mode = self.animation.mode;
+        let cycle_len = mode.get_cycle_len(frames.len());
+
+        (0..self.n_crossed_frames)
+            .map(move |i| {
+                let cycle_idx =
+                    (self.first_crossed_cycle_idx + i) % cycle_len;
+                &frames[mode.get_frame_idx(cycle_idx, frames.len())]
+            })
+            .flat_map(|frame| frame.events.iter().map(|e| e.as_str()))
+    }
+
+    pub fn check_if_event_crossed(
+        &self,
+        atlas: &FrameAtlas,
+        name: &str,
+    ) -> bool {
+        self.get_events(atlas).any(|event| event == name)
     }
 
-    pub fn update(&mut self, dt: f32) -> (u128, Option<&Frame>) {
+    pub fn update<'a>(
+        &mut self,
+        atlas: &'a FrameAtlas,
+        dt: f32,
+    ) -> (u128, Option<&'a Frame>) {
         if !self.is_started {
             return (0, None);
         }
 
-        let frames = self
-            .atlas
-            .name_to_frames
-            .get(self.name)
-            .expect(&format!("FrameAtlas should have {}", self.name));
-        let n_frames = frames.len() as f32;
-        let max_idx = n_frames - 1.0;
+        if let Some(progress) = self.matched_progress.take() {
+            let durations = self.get_cycle_durations(atlas);
+            self.time = progress * durations.iter().sum::<f32>();
+            self.cycle_idx = Some(get_cycle_idx(&durations, self.time));
+        }
+
+        if !self.is_paused {
+            self.time += dt * self.speed.max(0.0);
+        }
+
+        let mut durations = self.get_cycle_durations(atlas);
+        let cycle_duration: f32 = durations.iter().sum();
+        if self.time >= cycle_duration {
+            if let Some(animation) = self.queue[0] {
+                let time = self.time - cycle_duration;
+                self.queue.rotate_left(1);
+                self.queue[MAX_N_QUEUED_ANIMATIONS - 1] = None;
+                self.start(animation);
+                self.time = time;
+                durations = self.get_cycle_durations(atlas);
+            }
+        }
+
+        // Animation without frames is finished right away and shows
+        // nothing
+        if durations.is_empty() {
+            self.time = 0.0;
+            self.progress = 1.0;
+            self.cycle_idx = None;
+            self.n_crossed_frames = 0;
+            return (self.animation_id, None);
+        }
 
-        self.progress += dt / (n_frames * self.frame_duration);
-        if self.is_repeat {
-            self.progress -= self.progress.floor();
+        let mode = self.animation.mode;
+        let cycle_len = durations.len();
+        let cycle_duration: f32 = durations.iter().sum();
+        let mut n_loops = 0;
+        if cycle_duration <= 0.0 {
+            // Zero-length animation is finished right away and shows its
+            // last frame
+            self.time = 0.0;
+            self.progress = 1.0;
         } else {
-            self.progress = self.progress.min(1.0);
+            if mode.is_loop() {
+                let n = (self.time / cycle_duration).floor();
+                n_loops = n as usize;
+                self.time -= n * cycle_duration;
+            } else {
+                self.time = self.time.min(cycle_duration);
+            }
+            self.progress = self.time / cycle_duration;
+        }
+
+        let cycle_idx = get_cycle_idx(&durations, self.time);
+        let n_passed_frames = n_loops * cycle_len + cycle_idx;
+        (self.first_crossed_cycle_idx, self.n_crossed_frames) =
+            match self.cycle_idx {
+                None => (0, n_passed_frames + 1),
+                Some(prev_idx) => (
+                    (prev_idx + 1) % cycle_len,
+                    n_passed_frames.saturating_sub(prev_idx),
+                ),
+            };
+        self.cycle_idx = Some(cycle_idx);
+
+        let frames = atlas.get_frames(self.animation.id);
+        let frame_idx = mode.get_frame_idx(cycle_idx, frames.len());
+
+        (self.animation_id, Some(&frames[frame_idx]))
+    }
+
+    fn start(&mut self, animation: Animation) {
+        self.is_started = true;
+        self.matched_progress = None;
+        self.animation = animation;
+        self.time = 0.0;
+        self.progress = 0.0;
+        self.cycle_idx = None;
+        self.n_crossed_frames = 0;
+        self.animation_id += 1;
+    }
+
+    fn get_cycle_durations(&self, atlas: &FrameAtlas) -> Vec<f32> {
+        let frames = atlas.get_frames(self.animation.id);
+        let mode = self.animation.mode;
+
+        (0..mode.get_cycle_len(frames.len()))
+            .map(|cycle_idx| {
+                let frame_idx =
+                    mode.get_frame_idx(cycle_idx, frames.len());
+                frames[frame_idx]
+                    .duration
+                    .unwrap_or(self.animation.frame_duration)
+            })
+            .collect()
+    }
+}
+
+/// Each frame is shown for its whole duration, the last one also stays
+/// after the non-repeating animation is finished.
+fn get_cycle_idx(durations: &[f32], time: f32) -> usize {
+    let mut frame_end = 0.0;
+    for (idx, duration) in durations.iter().enumerate() {
+        frame_end += duration;
+        if time < frame_end {
+            return idx;
+        }
+    }
+
+    durations.len().saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Assets;
+
+    fn new_frame() -> Frame {
+        let meta = r#"{
+            "sprite": {"x": 0, "y": 0, "w": 10, "h": 20},
+            "masks": {"rigid": {"x": 1, "y": 15, "w": 3, "h": 4}}
+        }"#;
+
+        serde_json::from_str(meta).unwrap()
+    }
+
+    fn assert_rect_eq(
+        rect: Rect,
+        bot_left: [f32; 2],
+        top_right: [f32; 2],
+    ) {
+        let expected = [bot_left, top_right];
+        let actual = [rect.get_bot_left(), rect.get_top_right()];
+        for (expected, actual) in expected.iter().zip(actual) {
+            assert!(
+                (actual.x - expected[0]).abs() < 1e-4
+                    && (actual.y - expected[1]).abs() < 1e-4,
+                "{:?} != {:?}",
+                [rect.get_bot_left(), rect.get_top_right()],
+                [bot_left, top_right],
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_mask_for_all_pivots_and_flips() {
+        let p = Vec2::new(100.0, 200.0);
+
+        // Pivots with the sprite bottom left corner they produce
+        let pivots = [
+            (Pivot::Center(p), [95.0, 190.0]),
+            (Pivot::BotCenter(p), [95.0, 200.0]),
+            (Pivot::TopCenter(p), [95.0, 180.0]),
+            (Pivot::BotLeft(p), [100.0, 200.0]),
+            (Pivot::TopLeft(p), [100.0, 180.0]),
+            (Pivot::TopRight(p), [90.0, 180.0]),
+            (Pivot::RightCenter(p), [90.0, 190.0]),
+            (Pivot::LeftCenter(p), [100.0, 190.0]),
+        ];
+
+        // Flips with the mask rect relative to the sprite bottom left
+        let flips = [
+            ((false, false), [1.0, 11.0], [4.0, 15.0]),
+            ((true, false), [6.0, 11.0], [9.0, 15.0]),
+            ((false, true), [1.0, 5.0], [4.0, 9.0]),
+            ((true, true), [6.0, 5.0], [9.0, 9.0]),
+        ];
+
+        let frame = new_frame();
+        for (pivot, sprite_bot_left) in pivots {
+            for ((flip_x, flip_y), bot_left, top_right) in flips {
+                let transform = FrameTransform {
+                    flip_x,
+                    flip_y,
+                    rotation: 0.0,
+                };
+                let rect =
+                    frame.get_mask("rigid", pivot, transform).unwrap();
+                let [x, y] = sprite_bot_left;
+
+                assert_rect_eq(
+                    rect,
+                    [x + bot_left[0], y + bot_left[1]],
+                    [x + top_right[0], y + top_right[1]],
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_mask_with_rotation() {
+        let frame = new_frame();
+        let pivot = Pivot::BotLeft(Vec2::zeros());
+        let transform = FrameTransform {
+            rotation: std::f32::consts::FRAC_PI_2,
+            ..Default::default()
         };
+        let rect = frame.get_mask("rigid", pivot, transform).unwrap();
+
+        assert_rect_eq(rect, [0.0, 6.0], [4.0, 9.0]);
+    }
+
+    #[test]
+    fn test_get_missing_mask() {
+        let frame = new_frame();
+        let pivot = Pivot::BotLeft(Vec2::zeros());
+
+        assert!(frame
+            .get_mask("hurt", pivot, FrameTransform::default())
+            .is_none());
+    }
+
+    fn new_atlas() -> FrameAtlas {
+        let frame =
+            r#"{"sprite": {"x": 0, "y": 0, "w": 1, "h": 1}, "masks": {}}"#;
+        let meta = format!(
+            r#"{{"size": [1, 1], "frames": {{"a": [{0}, {0}], "b": [{0}]}}}}"#,
+            frame
+        );
+
+        serde_json::from_str(&meta).unwrap()
+    }
+
+    #[test]
+    fn test_animation_id_changes_on_restart() {
+        let mut assets = Assets::new();
+        let handle = assets.insert_atlas(new_atlas());
+        let atlas = assets.get_atlas(handle).unwrap();
+        let (a, b) = (
+            atlas.get_animation_id("a").unwrap(),
+            atlas.get_animation_id("b").unwrap(),
+        );
+        let mut animator = assets.new_animator(handle).unwrap();
+        assert_eq!(animator.update(atlas, 0.1).0, 0);
+
+        animator.play(a, 0.1, PlayMode::Once);
+        let a_id = animator.update(atlas, 0.1).0;
+        animator.play(a, 0.1, PlayMode::Once);
+        assert_eq!(animator.update(atlas, 0.1).0, a_id);
+
+        animator.play(b, 0.1, PlayMode::Once);
+        let b_id = animator.update(atlas, 0.1).0;
+        assert_ne!(b_id, a_id);
+
+        animator.play(a, 0.1, PlayMode::Once);
+        let id = animator.update(atlas, 0.1).0;
+        assert_ne!(id, a_id);
+        assert_ne!(id, b_id);
+    }
+
+    #[test]
+    fn test_animation_id_changes_on_queued_start() {
+        let mut assets = Assets::new();
+        let handle = assets.insert_atlas(new_atlas());
+        let atlas = assets.get_atlas(handle).unwrap();
+        let a = atlas.get_animation_id("a").unwrap();
+        let mut animator = assets.new_animator(handle).unwrap();
+        animator.play(a, 0.1, PlayMode::Once);
+        animator.queue(a, 0.1, PlayMode::Once);
+        let id = animator.update(atlas, 0.15).0;
+
+        assert_ne!(animator.update(atlas, 0.1).0, id);
+    }
+
+    #[test]
+    fn test_animation_id_is_deterministic() {
+        let mut assets = Assets::new();
+        let handle = assets.insert_atlas(new_atlas());
+        let atlas = assets.get_atlas(handle).unwrap();
+        let ids: Vec<Vec<u128>> = (0..2)
+            .map(|_| {
+                let mut animator = assets.new_animator(handle).unwrap();
+                ["a", "b", "b", "a"]
+                    .iter()
+                    .map(|name| {
+                        let id = atlas.get_animation_id(name).unwrap();
+                        animator.play(id, 0.1, PlayMode::Loop);
+                        animator.update(atlas, 0.1).0
+                    })
+                    .collect()
+            })
+            .collect();
+
+        assert_eq!(ids[0], ids[1]);
+    }
+
+    /// Animation of the frames with the sprite x equal to their index.
+    fn new_numbered_atlas(durations: &[Option<f32>]) -> FrameAtlas {
+        let frames: Vec<_> = durations
+            .iter()
+            .enumerate()
+            .map(|(x, duration)| {
+                let duration = duration.map_or(String::new(), |d| {
+                    format!(r#", "duration": {}"#, d)
+                });
+                format!(
+                    r#"{{"sprite": {{"x": {}, "y": 0, "w": 1, "h": 1}}, "masks": {{}}{}}}"#,
+                    x, duration
+                )
+            })
+            .collect();
+        let meta = format!(
+            r#"{{"size": [8, 1], "frames": {{"a": [{}]}}}}"#,
+            frames.join(", ")
+        );
+
+        serde_json::from_str(&meta).unwrap()
+    }
+
+    /// Sprite x of the frames shown after each of the updates.
+    fn play_frames(
+        atlas: FrameAtlas,
+        mode: PlayMode,
+        speed: f32,
+        dts: &[f32],
+    ) -> Vec<u32> {
+        let mut assets = Assets::new();
+        let handle = assets.insert_atlas(atlas);
+        let atlas = assets.get_atlas(handle).unwrap();
+        let mut animator = assets.new_animator(handle).unwrap();
+        animator.play(atlas.get_animation_id("a").unwrap(), 1.0, mode);
+        animator.speed = speed;
 
-        let idx = (self.progress * max_idx).round() as usize;
+        dts.iter()
+            .map(|dt| animator.update(atlas, *dt).1.unwrap().sprite.x)
+            .collect()
+    }
+
+    #[test]
+    fn test_frames_are_shown_for_equal_time() {
+        let atlas = new_numbered_atlas(&[None; 3]);
+        let frames = play_frames(atlas, PlayMode::Once, 1.0, &[0.5; 7]);
+
+        assert_eq!(frames, [0, 1, 1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_frames_use_own_durations() {
+        let atlas = new_numbered_atlas(&[Some(0.5), None, Some(2.0)]);
+        let frames = play_frames(atlas, PlayMode::Loop, 1.0, &[0.5; 8]);
+
+        assert_eq!(frames, [1, 1, 2, 2, 2, 2, 0, 1]);
+    }
+
+    #[test]
+    fn test_reverse_and_ping_pong_frame_order() {
+        let atlas = || new_numbered_atlas(&[None; 3]);
+        let reverse =
+            play_frames(atlas(), PlayMode::ReverseLoop, 1.0, &[1.0; 4]);
+        let ping_pong =
+            play_frames(atlas(), PlayMode::PingPong, 1.0, &[1.0; 6]);
+
+        assert_eq!(reverse, [1, 0, 2, 1]);
+        assert_eq!(ping_pong, [1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_speed_scales_time() {
+        let atlas = || new_numbered_atlas(&[None; 4]);
+
+        assert_eq!(
+            play_frames(atlas(), PlayMode::Once, 2.0, &[0.5; 3]),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            play_frames(atlas(), PlayMode::Once, 0.5, &[1.0; 3]),
+            [0, 1, 1]
+        );
+        assert_eq!(
+            play_frames(atlas(), PlayMode::Loop, -1.0, &[1.0; 3]),
+            [0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_zero_length_animation_shows_last_frame() {
+        let mut assets = Assets::new();
+        let atlas = new_numbered_atlas(&[Some(0.0), Some(0.0)]);
+        let handle = assets.insert_atlas(atlas);
+        let atlas = assets.get_atlas(handle).unwrap();
+        let mut animator = assets.new_animator(handle).unwrap();
+        animator.play(
+            atlas.get_animation_id("a").unwrap(),
+            0.0,
+            PlayMode::Loop,
+        );
+
+        assert_eq!(animator.update(atlas, 0.1).1.unwrap().sprite.x, 1);
+        assert_eq!(animator.progress, 1.0);
+    }
+
+    #[test]
+    fn test_animation_without_frames_shows_nothing() {
+        let meta = r#"{"size": [1, 1], "frames": {"a": []}}"#;
+        let mut assets = Assets::new();
+        let handle =
+            assets.insert_atlas(serde_json::from_str(meta).unwrap());
+        let atlas = assets.get_atlas(handle).unwrap();
+        let id = atlas.get_animation_id("a").unwrap();
+
+        for mode in [
+            PlayMode::Once,
+            PlayMode::Loop,
+            PlayMode::Reverse,
+            PlayMode::ReverseLoop,
+            PlayMode::PingPong,
+        ] {
+            let mut animator = assets.new_animator(handle).unwrap();
+            animator.play(id, 0.1, mode);
+            for dt in [0.0, 0.1, 1.0] {
+                let (_, frame) = animator.update(atlas, dt);
+                assert!(frame.is_none());
+                assert_eq!(animator.get_events(atlas).count(), 0);
+            }
+            assert!(animator.is_finished() || mode.is_loop());
+        }
+    }
+
+    #[test]
+    fn test_immediate_replay_clears_queue() {
+        let mut assets = Assets::new();
+        let handle = assets.insert_atlas(new_atlas());
+        let atlas = assets.get_atlas(handle).unwrap();
+        let (a, b) = (
+            atlas.get_animation_id("a").unwrap(),
+            atlas.get_animation_id("b").unwrap(),
+        );
+        let mut animator = assets.new_animator(handle).unwrap();
+        animator.play(a, 0.1, PlayMode::Loop);
+        animator.play_with_transition(
+            b,
+            0.1,
+            PlayMode::Loop,
+            Transition::FinishCycle,
+        );
+        assert!(animator.queue(b, 0.1, PlayMode::Once));
+
+        animator.play_with_transition(
+            a,
+            0.1,
+            PlayMode::Loop,
+            Transition::Immediate,
+        );
+        assert!(animator.queue.iter().all(|slot| slot.is_none()));
+
+        animator.update(atlas, 10.0);
+        assert_eq!(animator.get_animation_id(), Some(a));
+    }
+
+    #[test]
+    fn test_queue_rejects_animations_when_full() {
+        let mut assets = Assets::new();
+        let handle = assets.insert_atlas(new_atlas());
+        let atlas = assets.get_atlas(handle).unwrap();
+        let (a, b) = (
+            atlas.get_animation_id("a").unwrap(),
+            atlas.get_animation_id("b").unwrap(),
+        );
+        let mut animator = assets.new_animator(handle).unwrap();
+        animator.play(a, 0.1, PlayMode::Once);
+
+        for _ in 0..MAX_N_QUEUED_ANIMATIONS {
+            assert!(animator.queue(a, 0.1, PlayMode::Once));
+        }
+        assert!(!animator.queue(b, 0.1, PlayMode::Once));
 
-        (self.animation_id, Some(&frames[idx]))
+        animator.update(atlas, 0.25);
+        assert!(animator.queue(b, 0.1, PlayMode::Once));
     }
 }
@@ -1,9 +1,18 @@
+use crate::assets::*;
+use crate::debug_draw::DebugDraw;
+use crate::error::{Error, Result};
 use crate::frame::*;
 use crate::input::*;
+use crate::level::*;
 use crate::renderer::*;
 use crate::vec::*;
+use crate::watcher::FileWatcher;
+use enum_iterator::{all, Sequence};
+use sdl2::keyboard::Keycode;
 use sdl2::EventPump;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 pub struct Camera {
     pub position: Vec2<f32>,
@@ -32,6 +41,18 @@ impl Camera {
 struct Debug {
     show_rigid_colliders: bool,
     show_attack_colliders: bool,
+    show_hurt_colliders: bool,
+    show_trigger_colliders: bool,
+    show_render_stats: bool,
+    show_velocities: bool,
+    show_behaviour_states: bool,
+}
+
+/// Watches the asset files, it's enabled only in debug builds.
+struct HotReload {
+    shaders: FileWatcher,
+    atlas: FileWatcher,
+    levels: FileWatcher,
 }
 
 enum Behaviour {
@@ -40,7 +61,7 @@ enum Behaviour {
     WolfAIBehaviour(WolfAI),
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Sequence)]
 enum KnightPlayerState {
     Idle,
     Run,
@@ -53,11 +74,73 @@ enum KnightPlayerState {
     JumpLanding,
 }
 
+impl KnightPlayerState {
+    fn get_animation_name(self) -> &'static str {
+        use KnightPlayerState::*;
+
+        match self {
+            Idle => "knight_idle",
+            Run => "knight_run",
+            Roll => "knight_roll",
+            Attack0 => "knight_attack_0",
+            Attack1 => "knight_attack_1",
+            Attack2 => "knight_attack_2",
+            JumpUp => "knight_jump_up",
+            JumpDown => "knight_jump_down",
+            JumpLanding => "knight_jump_landing",
+        }
+    }
+
+    fn from_animation_name(name: &str) -> Option<Self> {
+        all::<Self>().find(|state| state.get_animation_name() == name)
+    }
+
+    fn play(
+        self,
+        atlas: &FrameAtlas,
+        animator: &mut FrameAnimator,
+    ) -> Result<()> {
+        use KnightPlayerState::*;
+
+        let id = atlas.get_animation_id(self.get_animation_name())?;
+        match self {
+            Idle | Run => animator.play_with_transition(
+                id,
+                0.07,
+                PlayMode::Loop,
+                Transition::MatchFrame,
+            ),
+            JumpUp | JumpDown => animator.play(id, 0.07, PlayMode::Once),
+            _ => {
+                animator.play(id, 0.07, PlayMode::Once);
+                Idle.queue(atlas, animator)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn queue(
+        self,
+        atlas: &FrameAtlas,
+        animator: &mut FrameAnimator,
+    ) -> Result<()> {
+        let id = atlas.get_animation_id(self.get_animation_name())?;
+        let mode = match self {
+            Self::Idle | Self::Run => PlayMode::Loop,
+            _ => PlayMode::Once,
+        };
+
+        animator.queue(id, 0.07, mode);
+
+        Ok(())
+    }
+}
+
 struct KnightPlayer {
     pub curr_state: KnightPlayerState,
-    pub next_state: KnightPlayerState,
     pub can_perform_combo: bool,
-    pub is_attack2_step_done: bool,
+    pub is_combo_window_open: bool,
     pub attack0_damage: f32,
     pub attack1_damage: f32,
     pub attack2_damage: f32,
@@ -81,9 +164,8 @@ impl KnightPlayer {
     ) -> Self {
         Self {
             curr_state: KnightPlayerState::Idle,
-            next_state: KnightPlayerState::Idle,
             can_perform_combo: false,
-            is_attack2_step_done: false,
+            is_combo_window_open: false,
             attack0_damage,
             attack1_damage,
             attack2_damage,
@@ -96,23 +178,73 @@ impl KnightPlayer {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum WolfAIState {
     Idle,
     Run,
     Attack,
 }
 
+impl WolfAIState {
+    fn from_animation_name(name: &str) -> Option<Self> {
+        use WolfAIState::*;
+
+        match name {
+            "wolf_idle" => Some(Idle),
+            "wolf_run" => Some(Run),
+            "wolf_attack_prepare" | "wolf_attack_bite" => Some(Attack),
+            _ => None,
+        }
+    }
+
+    fn play(
+        self,
+        atlas: &FrameAtlas,
+        animator: &mut FrameAnimator,
+    ) -> Result<()> {
+        use WolfAIState::*;
+
+        let id = |name| atlas.get_animation_id(name);
+        match self {
+            Idle => animator.play_with_transition(
+                id("wolf_idle")?,
+                0.07,
+                PlayMode::Loop,
+                Transition::MatchFrame,
+            ),
+            Run => animator.play_with_transition(
+                id("wolf_run")?,
+                0.07,
+                PlayMode::Loop,
+                Transition::MatchFrame,
+            ),
+            Attack => {
+                animator.play(
+                    id("wolf_attack_prepare")?,
+                    0.07,
+                    PlayMode::Once,
+                );
+                animator.queue(
+                    id("wolf_attack_bite")?,
+                    0.07,
+                    PlayMode::Once,
+                );
+                animator.queue(id("wolf_idle")?, 0.07, PlayMode::Loop);
+            }
+        }
+
+        Ok(())
+    }
+}
+
 struct WolfAI {
     pub curr_state: WolfAIState,
-    pub next_state: WolfAIState,
 }
 
 impl WolfAI {
     pub fn new() -> Self {
         Self {
             curr_state: WolfAIState::Idle,
-            next_state: WolfAIState::Idle,
         }
     }
 }
@@ -132,62 +264,154 @@ impl Kinematic {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+enum CollisionLayer {
+    World = 1 << 0,
+    Player = 1 << 1,
+    Enemy = 1 << 2,
+    Hitbox = 1 << 3,
+    Hurtbox = 1 << 4,
+    Trigger = 1 << 5,
+}
+/// Layers the collider belongs to and the mask of layers it interacts
+/// with. The check is one-sided: the mask of the collider which is
+/// being resolved decides.
 #[derive(Clone, Copy, Default)]
-struct Attack {
-    id: u128,
-    collider: Option<Rect>,
+struct Collision {
+    layers: u32,
+    mask: u32,
+}
+
+impl Collision {
+    pub fn new(
+        layers: &[CollisionLayer],
+        mask: &[CollisionLayer],
+    ) -> Self {
+        let to_bits = |layers: &[CollisionLayer]| {
+            layers.iter().fold(0, |bits, layer| bits | *layer as u32)
+        };
+
+        Self {
+            layers: to_bits(layers),
+            mask: to_bits(mask),
+        }
+    }
+
+    pub fn check_if_on_layer(&self, layer: CollisionLayer) -> bool {
+        self.layers & layer as u32 != 0
+    }
+
+    pub fn check_if_interacts_with(&self, other: Collision) -> bool {
+        self.mask & other.layers != 0
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Hitbox {
+    collider: Rect,
     damage: f32,
-    is_player_friendly: bool,
+    knockback: Vec2<f32>,
+    hit_stop: f32,
+    is_global_hit_stop: bool,
+    rehit_interval: Option<f32>,
+    is_piercing: bool,
+}
 
-    attacked_entitiy_ids: [usize; 32],
-    n_attacked_entities: usize,
+#[derive(Clone, Default)]
+struct Attack {
+    id: u128,
+    time: f32,
+    hitboxes: Vec<Hitbox>,
+    collision: Collision,
+
+    /// Attack time of the last hit for each of the attacked entities
+    hit_times: HashMap<usize, f32>,
+    /// The first entity hit by a non-piercing hitbox
+    target_idx: Option<usize>,
 }
 
 impl Attack {
-    pub fn update(
-        &mut self,
-        id: u128,
-        collider: Option<Rect>,
-        damage: f32,
-        is_player_friendly: bool,
-    ) {
+    pub fn new(target_layers: &[CollisionLayer]) -> Self {
+        Self {
+            collision: Collision::new(
+                &[CollisionLayer::Hitbox],
+                target_layers,
+            ),
+            ..Default::default()
+        }
+    }
+
+    pub fn update(&mut self, id: u128, hitboxes: Vec<Hitbox>) {
         if self.id != id {
-            self.n_attacked_entities = 0;
+            self.time = 0.0;
+            self.hit_times.clear();
+            self.target_idx = None;
         }
 
         self.id = id;
-        self.collider = collider;
-        self.damage = damage;
-        self.is_player_friendly = is_player_friendly;
+        self.hitboxes = hitboxes;
     }
 
-    pub fn attack_entity(&mut self, entity_id: usize) {
-        if self.n_attacked_entities < self.attacked_entitiy_ids.len() {
-            for id in self.attacked_entitiy_ids
-                [0..self.n_attacked_entities]
-                .iter()
+    /// The strongest of the piercing or non-piercing hitboxes which
+    /// overlap any of the target hurtboxes and are allowed to hit the
+    /// target.
+    pub fn get_hit(
+        &self,
+        position: Vec2<f32>,
+        target_idx: usize,
+        target_hurtboxes: &[Rect],
+        target_position: Vec2<f32>,
+        is_piercing: bool,
+    ) -> Option<Hitbox> {
+        let mut hit: Option<Hitbox> = None;
+        for hitbox in self.hitboxes.iter() {
+            if hitbox.is_piercing != is_piercing
+                || !self.check_if_can_hit(target_idx, hitbox)
             {
-                if *id == entity_id {
-                    return;
-                }
+                continue;
             }
 
-            self.attacked_entitiy_ids[self.n_attacked_entities] =
-                entity_id;
-            self.n_attacked_entities += 1;
+            let collider = hitbox.collider.translate(position);
+            let is_hit = target_hurtboxes.iter().any(|hurtbox| {
+                hurtbox
+                    .translate(target_position)
+                    .check_if_collides_with_rect(collider)
+            });
+
+            if is_hit && hit.is_none_or(|hit| hitbox.damage > hit.damage) {
+                hit = Some(*hitbox);
+            }
+        }
+
+        hit
+    }
+
+    pub fn attack_entity(&mut self, entity_idx: usize, hitbox: &Hitbox) {
+        self.hit_times.insert(entity_idx, self.time);
+        if !hitbox.is_piercing && self.target_idx.is_none() {
+            self.target_idx = Some(entity_idx);
         }
     }
 
-    pub fn check_if_entity_attacked(&self, entity_id: usize) -> bool {
-        for id in
-            self.attacked_entitiy_ids[0..self.n_attacked_entities].iter()
+    /// Non-piercing hitboxes stick to the first attacked entity. An
+    /// entity is hit again only after the hitbox re-hit interval.
+    fn check_if_can_hit(
+        &self,
+        entity_idx: usize,
+        hitbox: &Hitbox,
+    ) -> bool {
+        if !hitbox.is_piercing
+            && self.target_idx.is_some_and(|idx| idx != entity_idx)
         {
-            if *id == entity_id {
-                return true;
-            }
+            return false;
         }
 
-        false
+        match self.hit_times.get(&entity_idx) {
+            None => true,
+            Some(hit_time) => hitbox
+                .rehit_interval
+                .is_some_and(|interval| self.time - hit_time >= interval),
+        }
     }
 }
 
@@ -203,35 +427,109 @@ impl Health {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TriggerEventType {
+    Enter,
+    Stay,
+    Exit,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TriggerEvent {
+    pub trigger_idx: usize,
+    pub entity_idx: usize,
+    pub event_type: TriggerEventType,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TriggerAction {
+    LevelTransition(usize),
+    Spawner(usize),
+    /// The player is re-spawned at the place where they entered the
+    /// checkpoint, when the level is re-spawned
+    Checkpoint,
+}
+
+struct Trigger {
+    collider: Rect,
+    action: TriggerAction,
+    overlapped_entity_ids: Vec<usize>,
+}
+
+impl Trigger {
+    pub fn new(collider: Rect, action: TriggerAction) -> Self {
+        Self {
+            collider,
+            action,
+            overlapped_entity_ids: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct DamageNumber {
+    position: Vec2<f32>,
+    damage: f32,
+    age: f32,
+}
+
 const MAX_N_ENTITIES: usize = 1024;
+const FLASH_DURATION: f32 = 0.1;
+const DAMAGE_NUMBER_LIFETIME: f32 = 0.8;
+const DAMAGE_NUMBER_SPEED: f32 = 30.0;
+const DAMAGE_NUMBER_FONT_SIZE: f32 = 10.0;
+const RENDER_STATS_FONT_SIZE: f32 = 20.0;
+const DEBUG_VELOCITY_SCALE: f32 = 0.1;
+const SCREENSHOTS_DIR: &str = "./screenshots";
 
 pub struct Game {
     dt: f32,
     prev_upd_time: Instant,
 
-    event_pump: &'static mut EventPump,
-    input: &'static mut Input,
-    frame_atlas: &'static FrameAtlas,
-    renderer: &'static mut Renderer,
+    event_pump: EventPump,
+    input: Input,
+    renderer: Renderer,
+    assets: Assets,
+    frame_atlas: AtlasHandle,
+    frame_atlas_texture: TextureHandle,
+    frame_atlas_meta_fp: String,
+    frame_atlas_image_fp: String,
+    hot_reload: Option<HotReload>,
     camera: Camera,
 
     gravity: f32,
+    friction: f32,
+    hit_stop: f32,
+    damage_numbers: Vec<DamageNumber>,
+
+    level_fps: Vec<String>,
+    level_idx: usize,
+    next_level_idx: Option<usize>,
+    player_idx: Option<usize>,
+    checkpoint: Option<Vec2<f32>>,
+    trigger_events: Vec<TriggerEvent>,
 
     n_entities: usize,
     positions: [Vec2<f32>; MAX_N_ENTITIES],
     behaviours: [Behaviour; MAX_N_ENTITIES],
     look_dirs: [f32; MAX_N_ENTITIES],
-    are_player_friendly: [bool; MAX_N_ENTITIES],
+    collisions: [Collision; MAX_N_ENTITIES],
+    owners: [Option<usize>; MAX_N_ENTITIES],
 
     frame_animators: [Option<FrameAnimator>; MAX_N_ENTITIES],
     kinematics: [Option<Kinematic>; MAX_N_ENTITIES],
     rigid_colliders: [Option<Rect>; MAX_N_ENTITIES],
+    hurt_colliders: [Vec<Rect>; MAX_N_ENTITIES],
     attacks: [Attack; MAX_N_ENTITIES],
     damages: [f32; MAX_N_ENTITIES],
     healths: [Option<Health>; MAX_N_ENTITIES],
     sprites: [Option<XYWH>; MAX_N_ENTITIES],
+    triggers: [Option<Trigger>; MAX_N_ENTITIES],
+    hit_stops: [f32; MAX_N_ENTITIES],
+    flash_timers: [f32; MAX_N_ENTITIES],
 
     debug: Debug,
+    debug_draw: DebugDraw,
 }
 
 impl Game {
@@ -239,68 +537,100 @@ impl Game {
         window_size: Vec2<u32>,
         frame_atlas_meta_fp: &str,
         frame_atlas_image_fp: &str,
-    ) -> Self {
+        level_fps: &[&str],
+    ) -> Result<Self> {
         let camera = Camera::new(Vec2::zeros());
-        let frame_atlas = Box::new(FrameAtlas::new(frame_atlas_meta_fp));
-        let input = Box::new(Input::new(window_size));
-
-        let sdl = sdl2::init().unwrap();
-        let event_pump = Box::new(sdl.event_pump().unwrap());
-        let renderer = Box::new(Renderer::new(
-            &sdl,
-            "Lift",
-            window_size,
-            frame_atlas_image_fp,
-        ));
+        let mut assets = Assets::new();
+        let frame_atlas = assets.load_atlas(frame_atlas_meta_fp)?;
+        let input = Input::new(window_size);
+
+        let sdl = sdl2::init().map_err(Error::Sdl)?;
+        let event_pump = sdl.event_pump().map_err(Error::Sdl)?;
+        let mut renderer = Renderer::new(&sdl, "Lift", window_size)?;
+        let frame_atlas_texture =
+            renderer.load_texture(frame_atlas_image_fp)?;
+
+        let hot_reload = cfg!(debug_assertions).then(|| HotReload {
+            shaders: FileWatcher::new(&SHADER_FPS),
+            atlas: FileWatcher::new(&[
+                frame_atlas_meta_fp,
+                frame_atlas_image_fp,
+            ]),
+            levels: FileWatcher::new(level_fps),
+        });
 
         let debug = Debug {
             show_rigid_colliders: true,
             show_attack_colliders: true,
+            show_hurt_colliders: false,
+            show_trigger_colliders: true,
+            show_render_stats: false,
+            show_velocities: true,
+            show_behaviour_states: true,
         };
 
-        Self {
+        Ok(Self {
             dt: 0.0,
             prev_upd_time: Instant::now(),
 
-            event_pump: Box::leak(event_pump),
-            input: Box::leak(input),
-            frame_atlas: Box::leak(frame_atlas),
-            renderer: Box::leak(renderer),
+            event_pump,
+            input,
+            renderer,
+            assets,
+            frame_atlas,
+            frame_atlas_texture,
+            frame_atlas_meta_fp: frame_atlas_meta_fp.to_string(),
+            frame_atlas_image_fp: frame_atlas_image_fp.to_string(),
+            hot_reload,
             camera,
 
             gravity: 400.0,
+            friction: 600.0,
+            hit_stop: 0.0,
+            damage_numbers: Vec::new(),
+
+            level_fps: level_fps.iter().map(|fp| fp.to_string()).collect(),
+            level_idx: 0,
+            next_level_idx: None,
+            player_idx: None,
+            checkpoint: None,
+            trigger_events: Vec::new(),
 
             n_entities: 0,
             positions: [Vec2::zeros(); MAX_N_ENTITIES],
             behaviours: [(); MAX_N_ENTITIES].map(|_| Behaviour::Static),
             look_dirs: [1.0; MAX_N_ENTITIES],
-            are_player_friendly: [false; MAX_N_ENTITIES],
+            collisions: [Collision::default(); MAX_N_ENTITIES],
+            owners: [None; MAX_N_ENTITIES],
 
             frame_animators: [(); MAX_N_ENTITIES].map(|_| None),
             kinematics: [(); MAX_N_ENTITIES].map(|_| None),
             rigid_colliders: [None; MAX_N_ENTITIES],
-            attacks: [Attack::default(); MAX_N_ENTITIES],
+            hurt_colliders: [(); MAX_N_ENTITIES].map(|_| Vec::new()),
+            attacks: [(); MAX_N_ENTITIES].map(|_| Attack::default()),
             damages: [0.0; MAX_N_ENTITIES],
             healths: [None; MAX_N_ENTITIES],
             sprites: [None; MAX_N_ENTITIES],
+            triggers: [(); MAX_N_ENTITIES].map(|_| None),
+            hit_stops: [0.0; MAX_N_ENTITIES],
+            flash_timers: [0.0; MAX_N_ENTITIES],
 
             debug,
-        }
+            debug_draw: DebugDraw::new(),
+        })
     }
 
-    pub fn start(&mut self) {
-        self.new_knight_player(Vec2::new(0.0, 0.0));
-        self.new_wolf_ai(Vec2::new(40.0, 0.0));
-        self.new_rigid_collider(
-            Pivot::TopCenter(Vec2::new(0.0, 0.0)),
-            Vec2::new(1000.0, 50.0),
-        );
+    pub fn start(&mut self) -> Result<()> {
+        self.spawn_level(0)?;
 
         while !self.input.should_quit {
             self.update_input();
-            self.update_world();
+            self.update_hot_reload();
+            self.update_world()?;
             self.update_renderer();
         }
+
+        Ok(())
     }
 
     fn update_input(&mut self) {
@@ -310,13 +640,191 @@ impl Game {
         self.input.update();
     }
 
-    fn update_world(&mut self) {
-        self.dt = self.prev_upd_time.elapsed().as_nanos() as f32 / 1.0e9;
-        self.update_behaviours();
-        self.update_frame_animators();
+    /// Reload failures are only printed, so the assets can be fixed
+    /// without restarting the game.
+    fn update_hot_reload(&mut self) {
+        let Some(hot_reload) = self.hot_reload.as_mut() else {
+            return;
+        };
+
+        let is_shaders_changed = !hot_reload.shaders.poll().is_empty();
+        let is_atlas_changed = !hot_reload.atlas.poll().is_empty();
+        let is_level_changed = !hot_reload.levels.poll().is_empty();
+
+        if is_shaders_changed {
+            match self.renderer.reload_programs() {
+                Ok(()) => println!("Shaders reloaded"),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+
+        // The level re-spawn loads the atlas again
+        if is_atlas_changed {
+            let texture = self.frame_atlas_texture;
+            let image_fp = &self.frame_atlas_image_fp;
+            if let Err(error) =
+                self.renderer.reload_texture(texture, image_fp)
+            {
+                eprintln!("{}", error);
+            }
+        }
+
+        if is_level_changed || is_atlas_changed {
+            match self.spawn_level(self.level_idx) {
+                Ok(()) => println!("Level {} re-spawned", self.level_idx),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+    }
+
+    fn update_world(&mut self) -> Result<()> {
+        let dt = self.prev_upd_time.elapsed().as_nanos() as f32 / 1.0e9;
+        self.dt = if self.hit_stop > 0.0 { 0.0 } else { dt };
+        self.update_hit_effects(dt);
+        self.update_behaviours()?;
+        self.update_frame_animators()?;
         self.update_kinematics();
         self.update_attacks();
+        self.update_triggers()?;
+        self.update_camera();
+
+        if let Some(level_idx) = self.next_level_idx.take() {
+            self.spawn_level(level_idx)?;
+        }
+
         self.prev_upd_time = Instant::now();
+
+        Ok(())
+    }
+
+    /// Hit effects run on the real time, so they keep going during the
+    /// global hit-stop.
+    fn update_hit_effects(&mut self, dt: f32) {
+        self.hit_stop = (self.hit_stop - dt).max(0.0);
+        for idx in 0..self.n_entities {
+            self.hit_stops[idx] = (self.hit_stops[idx] - dt).max(0.0);
+            self.flash_timers[idx] =
+                (self.flash_timers[idx] - dt).max(0.0);
+        }
+
+        for damage_number in self.damage_numbers.iter_mut() {
+            damage_number.age += dt;
+            damage_number.position.y += DAMAGE_NUMBER_SPEED * dt;
+        }
+        self.damage_numbers
+            .retain(|number| number.age < DAMAGE_NUMBER_LIFETIME);
+    }
+
+    fn get_entity_dt(&self, idx: usize) -> f32 {
+        if self.hit_stops[idx] > 0.0 {
+            0.0
+        } else {
+            self.dt
+        }
+    }
+
+    fn update_camera(&mut self) {
+        if let Some(idx) = self.player_idx {
+            self.camera.position = self.positions[idx];
+        }
+    }
+
+    /// Queues the debug shapes of the entities, which are enabled in
+    /// the `Debug` settings. Compiled only in the debug builds or with
+    /// the `debug_draw` feature.
+    #[cfg(any(debug_assertions, feature = "debug_draw"))]
+    fn draw_debug(&mut self) {
+        for idx in 0..self.n_entities {
+            if let (Some(mut rect), true) = (
+                self.rigid_colliders[idx],
+                self.debug.show_rigid_colliders,
+            ) {
+                rect = rect.translate(self.positions[idx]);
+                self.debug_draw.rect(
+                    rect,
+                    Color::red(1.0),
+                    SpaceType::WorldSpace,
+                );
+            }
+
+            if self.debug.show_hurt_colliders {
+                for rect in self.hurt_colliders[idx].iter() {
+                    let rect = rect.translate(self.positions[idx]);
+                    self.debug_draw.rect(
+                        rect,
+                        Color::blue(1.0),
+                        SpaceType::WorldSpace,
+                    );
+                }
+            }
+
+            if self.debug.show_attack_colliders {
+                for hitbox in self.attacks[idx].hitboxes.iter() {
+                    let rect =
+                        hitbox.collider.translate(self.positions[idx]);
+                    self.debug_draw.rect(
+                        rect,
+                        Color::yellow(1.0),
+                        SpaceType::WorldSpace,
+                    );
+                }
+            }
+
+            if let (Some(trigger), true) = (
+                self.triggers[idx].as_ref(),
+                self.debug.show_trigger_colliders,
+            ) {
+                let rect = trigger.collider.translate(self.positions[idx]);
+                self.debug_draw.rect(
+                    rect,
+                    Color::green(0.5),
+                    SpaceType::WorldSpace,
+                );
+            }
+
+            if let (Some(kinematic), true) =
+                (self.kinematics[idx], self.debug.show_velocities)
+            {
+                let start = self.rigid_colliders[idx].map_or(
+                    self.positions[idx],
+                    |rect| {
+                        rect.translate(self.positions[idx]).get_center()
+                    },
+                );
+                let end =
+                    start + kinematic.velocity.scale(DEBUG_VELOCITY_SCALE);
+                self.debug_draw.arrow(
+                    start,
+                    end,
+                    Color::yellow(1.0),
+                    SpaceType::WorldSpace,
+                );
+            }
+
+            if self.debug.show_behaviour_states {
+                let state = match &self.behaviours[idx] {
+                    Behaviour::Static => None,
+                    Behaviour::KnightPlayerBehaviour(knight) => {
+                        Some(format!("{:?}", knight.curr_state))
+                    }
+                    Behaviour::WolfAIBehaviour(wolf) => {
+                        Some(format!("{:?}", wolf.curr_state))
+                    }
+                };
+                if let (Some(state), Some(sprite)) =
+                    (state, self.sprites[idx])
+                {
+                    let position =
+                        self.positions[idx].add_y(sprite.h as f32);
+                    self.debug_draw.text(
+                        &state,
+                        position,
+                        Color::green(1.0),
+                        SpaceType::WorldSpace,
+                    );
+                }
+            }
+        }
     }
 
     fn update_renderer(&mut self) {
@@ -329,70 +837,132 @@ impl Game {
                 let position = self.positions[idx];
                 let pivot = Pivot::BotCenter(position);
                 let apply_light = false;
-                let flip = self.look_dirs[idx] < 0.0;
-                let primitive = DrawPrimitive::world_sprite(
+                let transform =
+                    FrameTransform::from_flip_x(self.look_dirs[idx] < 0.0);
+                let mut primitive = DrawPrimitive::world_sprite(
+                    self.frame_atlas_texture,
                     sprite,
                     pivot,
                     apply_light,
-                    flip,
+                    transform,
                 );
+                if self.flash_timers[idx] > 0.0 {
+                    primitive.flash = Color::gray(1.0, 1.0);
+                }
 
                 self.renderer.push_primitive(primitive);
             }
+        }
 
-            if let (Some(mut rect), true) = (
-                self.rigid_colliders[idx],
-                self.debug.show_rigid_colliders,
-            ) {
-                rect = rect.translate(self.positions[idx]);
-                let primitive =
-                    DrawPrimitive::world_rect(rect, Color::red(0.2));
-                self.renderer.push_primitive(primitive);
-            }
+        #[cfg(any(debug_assertions, feature = "debug_draw"))]
+        self.draw_debug();
+
+        for damage_number in self.damage_numbers.iter() {
+            let alpha = 1.0 - damage_number.age / DAMAGE_NUMBER_LIFETIME;
+            self.renderer.push_text(
+                &format!("{}", damage_number.damage),
+                Pivot::BotCenter(damage_number.position),
+                SpaceType::WorldSpace,
+                RenderLayer::Effects,
+                DAMAGE_NUMBER_FONT_SIZE,
+                Color::new(1.0, 1.0, 1.0, alpha),
+            );
+        }
 
-            if let (Some(mut rect), true) = (
-                self.attacks[idx].collider,
-                self.debug.show_attack_colliders,
-            ) {
-                rect = rect.translate(self.positions[idx]);
-                let primitive =
-                    DrawPrimitive::world_rect(rect, Color::yellow(0.2));
-                self.renderer.push_primitive(primitive);
-            }
+        if self.debug.show_render_stats {
+            // The stats are from the previous frame
+            let stats = self.renderer.get_stats();
+            self.renderer.push_text(
+                &format!(
+                    "primitives: {}, batches: {}",
+                    stats.n_primitives, stats.n_batches
+                ),
+                Pivot::TopLeft(Vec2::new(
+                    10.0,
+                    self.input.window_size.y as f32 - 10.0,
+                )),
+                SpaceType::ScreenSpace,
+                RenderLayer::UI,
+                RENDER_STATS_FONT_SIZE,
+                Color::new(1.0, 1.0, 1.0, 1.0),
+            );
         }
 
+        self.debug_draw.flush(&mut self.renderer);
         self.renderer.render();
+
+        if self.input.key_is_pressed(Keycode::F12) {
+            match self.save_screenshot() {
+                Ok(fp) => println!("Screenshot saved to {}", fp),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+    }
+
+    /// Renders the current frame once more, offscreen, and saves it.
+    fn save_screenshot(&mut self) -> Result<String> {
+        let image = self.renderer.screenshot()?;
+
+        fs::create_dir_all(SCREENSHOTS_DIR)
+            .map_err(Error::io(SCREENSHOTS_DIR))?;
+        let timestamp =
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let fp = format!(
+            "{}/screenshot_{}.png",
+            SCREENSHOTS_DIR,
+            timestamp.as_millis()
+        );
+        image.save(&fp).map_err(|source| Error::ImageSave {
+            fp: fp.clone(),
+            source,
+        })?;
+
+        Ok(fp)
     }
 
-    fn update_behaviours(&mut self) {
+    fn update_behaviours(&mut self) -> Result<()> {
         use Behaviour::*;
 
         for idx in 0..self.n_entities {
+            let dt = self.get_entity_dt(idx);
             match self.behaviours[idx] {
                 KnightPlayerBehaviour(ref mut knight) => {
+                    let animator =
+                        self.frame_animators[idx].as_mut().unwrap();
+                    let motion = Motion {
+                        dt,
+                        position: &mut self.positions[idx],
+                        kinematic: self.kinematics[idx].as_mut().unwrap(),
+                        look_dir: &mut self.look_dirs[idx],
+                    };
                     update_knight_player(
                         knight,
-                        self.input,
-                        self.dt,
-                        &mut self.positions[idx],
-                        &mut self.kinematics[idx].as_mut().unwrap(),
-                        &mut self.frame_animators[idx].as_mut().unwrap(),
+                        &mut self.input,
+                        motion,
+                        self.assets
+                            .get_atlas(animator.get_atlas_handle())?,
+                        animator,
                         &mut self.damages[idx],
-                        &mut self.look_dirs[idx],
-                    );
+                    )?;
                 }
                 WolfAIBehaviour(ref mut wolf) => {
                     println!("{:?}", self.healths[idx].unwrap().curr);
+                    let animator =
+                        self.frame_animators[idx].as_mut().unwrap();
                     update_wolf_ai(
                         wolf,
                         &mut self.kinematics[idx].as_mut().unwrap(),
-                        &mut self.frame_animators[idx].as_mut().unwrap(),
+                        self.assets
+                            .get_atlas(animator.get_atlas_handle())?,
+                        animator,
                         &mut self.look_dirs[idx],
-                    );
+                    )?;
                 }
                 Static => {}
             }
         }
+
+        Ok(())
     }
 
     fn update_kinematics(&mut self) {
@@ -404,22 +974,34 @@ impl Game {
                     continue;
                 };
 
-            kinematic.velocity.y -= self.gravity * self.dt;
-            self.positions[idx] += kinematic.velocity.scale(self.dt);
+            let dt = self.get_entity_dt(idx);
+            if kinematic.is_grounded {
+                let friction =
+                    (self.friction * dt).min(kinematic.velocity.x.abs());
+                kinematic.velocity.x -=
+                    kinematic.velocity.x.signum() * friction;
+            }
+
+            kinematic.velocity.y -= self.gravity * dt;
+            self.positions[idx] += kinematic.velocity.scale(dt);
 
             if let Some(mut collider) = self.rigid_colliders[idx] {
                 collider = collider.translate(self.positions[idx]);
                 let mut is_grounded = false;
 
                 for other_idx in 0..self.n_entities {
-                    if idx == other_idx {
+                    if idx == other_idx
+                        || check_if_related(&self.owners, idx, other_idx)
+                        || !self.collisions[idx].check_if_interacts_with(
+                            self.collisions[other_idx],
+                        )
+                    {
                         continue;
                     }
 
-                    if let (Some(mut other_collider), None) = (
-                        self.rigid_colliders[other_idx],
-                        self.kinematics[other_idx],
-                    ) {
+                    if let Some(mut other_collider) =
+                        self.rigid_colliders[other_idx]
+                    {
                         other_collider = other_collider
                             .translate(self.positions[other_idx]);
                         let mtv = collider.collide_aabb(other_collider);
@@ -445,49 +1027,136 @@ impl Game {
     }
 
     fn update_attacks(&mut self) {
-        for idx in 0..self.n_entities {
-            let mut attack = self.attacks[idx];
+        let n_entities = self.n_entities;
+        for idx in 0..n_entities {
+            let mut attack = std::mem::take(&mut self.attacks[idx]);
+            attack.time += self.get_entity_dt(idx);
+
+            let hits = get_attack_hits(
+                &attack,
+                idx,
+                &self.positions[..n_entities],
+                &self.owners[..n_entities],
+                &self.collisions[..n_entities],
+                &self.healths[..n_entities],
+                &self.hurt_colliders[..n_entities],
+            );
+            for (other_idx, hitbox) in hits {
+                attack.attack_entity(other_idx, &hitbox);
+                self.hit_entity(idx, other_idx, hitbox);
+            }
 
-            if let Some(mut collider) = attack.collider {
-                collider = collider.translate(self.positions[idx]);
+            self.attacks[idx] = attack;
+        }
+    }
 
-                for other_idx in 0..self.n_entities {
-                    if idx == other_idx {
-                        continue;
-                    }
+    fn hit_entity(
+        &mut self,
+        idx: usize,
+        other_idx: usize,
+        hitbox: Hitbox,
+    ) {
+        self.healths[other_idx].as_mut().unwrap().curr -= hitbox.damage;
 
-                    let is_player_friendly =
-                        self.are_player_friendly[other_idx];
-                    let is_already_attacked =
-                        attack.check_if_entity_attacked(other_idx);
-                    if let (
-                        false,
-                        true,
-                        Some(mut other_collider),
-                        Some(mut health),
-                    ) = (
-                        is_already_attacked,
-                        is_player_friendly ^ attack.is_player_friendly,
-                        self.rigid_colliders[other_idx],
-                        self.healths[other_idx].as_mut(),
-                    ) {
-                        other_collider = other_collider
-                            .translate(self.positions[other_idx]);
-                        if other_collider
-                            .check_if_collides_with_rect(collider)
-                        {
-                            attack.attack_entity(other_idx);
-                            health.curr -= attack.damage;
-                        }
+        if let Some(kinematic) = self.kinematics[other_idx].as_mut() {
+            kinematic.velocity += hitbox.knockback;
+        }
+
+        if hitbox.is_global_hit_stop {
+            self.hit_stop = self.hit_stop.max(hitbox.hit_stop);
+        } else {
+            self.hit_stops[idx] = self.hit_stops[idx].max(hitbox.hit_stop);
+            self.hit_stops[other_idx] =
+                self.hit_stops[other_idx].max(hitbox.hit_stop);
+        }
+
+        self.flash_timers[other_idx] = FLASH_DURATION;
+
+        let mut position = self.positions[other_idx];
+        if let Some(collider) = self.rigid_colliders[other_idx] {
+            position.y += collider.get_y_max();
+        }
+        self.damage_numbers.push(DamageNumber {
+            position,
+            damage: hitbox.damage,
+            age: 0.0,
+        });
+    }
+
+    fn update_triggers(&mut self) -> Result<()> {
+        self.trigger_events.clear();
+
+        for idx in 0..self.n_entities {
+            let mut trigger =
+                if let Some(trigger) = self.triggers[idx].take() {
+                    trigger
+                } else {
+                    continue;
+                };
+
+            let n_entities = self.n_entities;
+            let collider = trigger.collider.translate(self.positions[idx]);
+            let overlapped_entity_ids = get_overlapped_entity_ids(
+                idx,
+                collider,
+                &self.positions[..n_entities],
+                &self.collisions[..n_entities],
+                &self.rigid_colliders[..n_entities],
+            );
+            self.trigger_events.extend(get_trigger_events(
+                idx,
+                &trigger.overlapped_entity_ids,
+                &overlapped_entity_ids,
+            ));
+
+            trigger.overlapped_entity_ids = overlapped_entity_ids;
+            self.triggers[idx] = Some(trigger);
+        }
+
+        for event_idx in 0..self.trigger_events.len() {
+            let event = self.trigger_events[event_idx];
+            let action =
+                self.triggers[event.trigger_idx].as_ref().unwrap().action;
+
+            match (action, event.event_type) {
+                (
+                    TriggerAction::LevelTransition(level_idx),
+                    TriggerEventType::Enter,
+                ) => {
+                    self.next_level_idx = Some(level_idx);
+                }
+                (
+                    TriggerAction::Spawner(n_spawns),
+                    TriggerEventType::Enter,
+                ) if n_spawns > 0 => {
+                    let position = self.positions[event.trigger_idx];
+                    if let Some(idx) = self.new_wolf_ai(position)? {
+                        self.owners[idx] = Some(event.trigger_idx);
                     }
+                    self.triggers[event.trigger_idx]
+                        .as_mut()
+                        .unwrap()
+                        .action = TriggerAction::Spawner(n_spawns - 1);
+                }
+                (TriggerAction::Checkpoint, TriggerEventType::Enter) => {
+                    self.checkpoint =
+                        Some(self.positions[event.entity_idx]);
                 }
+                _ => {}
             }
-
-            self.attacks[idx] = attack;
         }
+
+        Ok(())
     }
 
-    fn update_frame_animators(&mut self) {
+    /// Events of the triggers from the last world update, so the
+    /// reactions which are not trigger actions (e.g. cutscene starts)
+    /// can be attached to them.
+    pub fn get_trigger_events(&self) -> &[TriggerEvent] {
+        &self.trigger_events
+    }
+
+    fn update_frame_animators(&mut self) -> Result<()> {
         for idx in 0..self.n_entities {
             let mut animator =
                 if let Some(animator) = self.frame_animators[idx] {
@@ -496,33 +1165,102 @@ impl Game {
                     continue;
                 };
 
-            if let (id, Some(frame)) = animator.update(self.dt) {
-                let position = self.positions[idx];
-                let flip = self.look_dirs[idx] < 0.0;
+            let dt = self.get_entity_dt(idx);
+            let atlas =
+                self.assets.get_atlas(animator.get_atlas_handle())?;
+            if let (id, Some(frame)) = animator.update(atlas, dt) {
+                let pivot = Pivot::BotCenter(Vec2::zeros());
+                let transform =
+                    FrameTransform::from_flip_x(self.look_dirs[idx] < 0.0);
                 self.sprites[idx] = Some(frame.sprite);
-                self.rigid_colliders[idx] = frame.get_mask(
-                    "rigid",
-                    Pivot::BotCenter(Vec2::zeros()),
-                    flip,
-                );
+                self.rigid_colliders[idx] =
+                    frame.get_mask("rigid", pivot, transform);
+
+                // Sprites without dedicated hurt masks are hurt by
+                // their rigid body
+                let mut hurt_colliders =
+                    frame.get_hurtboxes(pivot, transform);
+                if hurt_colliders.is_empty() {
+                    hurt_colliders.extend(self.rigid_colliders[idx]);
+                }
+                self.hurt_colliders[idx] = hurt_colliders;
 
-                let collider = frame.get_mask(
-                    "attack",
-                    Pivot::BotCenter(Vec2::zeros()),
-                    flip,
-                );
                 let damage = self.damages[idx];
-                let is_player_friendly = self.are_player_friendly[idx];
-                self.attacks[idx].update(
-                    id,
-                    collider,
-                    damage,
-                    is_player_friendly,
-                );
+                let hitboxes = frame
+                    .get_hitboxes(pivot, transform)
+                    .into_iter()
+                    .map(|(collider, data)| Hitbox {
+                        collider,
+                        damage: damage * data.damage_scale,
+                        knockback: Vec2::new(
+                            data.knockback[0],
+                            data.knockback[1],
+                        ),
+                        hit_stop: data.hit_stop,
+                        is_global_hit_stop: data.is_global_hit_stop,
+                        rehit_interval: data.rehit_interval,
+                        is_piercing: data.is_piercing,
+                    })
+                    .collect();
+                self.attacks[idx].update(id, hitboxes);
             }
 
             self.frame_animators[idx] = Some(animator);
         }
+
+        Ok(())
+    }
+
+    /// The atlas is loaded again for each level, so the changes are
+    /// picked up and entities of the previous level can't outlive it.
+    fn spawn_level(&mut self, level_idx: usize) -> Result<()> {
+        let level = Level::new(&self.level_fps[level_idx])?;
+        let frame_atlas =
+            self.assets.load_atlas(&self.frame_atlas_meta_fp)?;
+        self.assets.unload_atlas(self.frame_atlas);
+        self.frame_atlas = frame_atlas;
+
+        // The checkpoint is kept only when the same level is re-spawned
+        if level_idx != self.level_idx {
+            self.checkpoint = None;
+        }
+        self.n_entities = 0;
+        self.player_idx = None;
+        self.damage_numbers.clear();
+        self.level_idx = level_idx;
+
+        let next_level_idx = (level_idx + 1) % self.level_fps.len();
+        for object in level.objects.iter() {
+            let rect = object.rect;
+            match object.name.as_str() {
+                "player" => {
+                    let position =
+                        self.checkpoint.unwrap_or(rect.get_bot_center());
+                    self.new_knight_player(position)?;
+                }
+                "collider" => {
+                    self.new_rigid_collider(
+                        Pivot::Center(rect.get_center()),
+                        rect.get_size(),
+                    );
+                }
+                "stair" => {
+                    let action =
+                        TriggerAction::LevelTransition(next_level_idx);
+                    self.new_trigger(rect, action);
+                }
+                "rat_nest" => {
+                    let rect = rect.expand_from_center(200.0, 100.0);
+                    self.new_trigger(rect, TriggerAction::Spawner(1));
+                }
+                "checkpoint" => {
+                    self.new_trigger(rect, TriggerAction::Checkpoint);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
     }
 
     fn new_entity(&mut self) -> Option<usize> {
@@ -533,10 +1271,29 @@ impl Game {
         let idx = self.n_entities;
         self.n_entities += 1;
 
+        // Entities slots are reused after a level re-spawn, so clean up
+        // everything which could be left from the previous owner
+        self.positions[idx] = Vec2::zeros();
+        self.behaviours[idx] = Behaviour::Static;
+        self.look_dirs[idx] = 1.0;
+        self.collisions[idx] = Collision::default();
+        self.owners[idx] = None;
+        self.frame_animators[idx] = None;
+        self.kinematics[idx] = None;
+        self.rigid_colliders[idx] = None;
+        self.hurt_colliders[idx].clear();
+        self.attacks[idx] = Attack::default();
+        self.damages[idx] = 0.0;
+        self.healths[idx] = None;
+        self.sprites[idx] = None;
+        self.triggers[idx] = None;
+        self.hit_stops[idx] = 0.0;
+        self.flash_timers[idx] = 0.0;
+
         return Some(idx);
     }
 
-    fn new_knight_player(&mut self, position: Vec2<f32>) {
+    fn new_knight_player(&mut self, position: Vec2<f32>) -> Result<()> {
         if let Some(idx) = self.new_entity() {
             let knight_player = KnightPlayer::new(
                 1.0, 2.0, 3.0, 100.0, 150.0, 150.0, 70.0, 8.0,
@@ -547,48 +1304,239 @@ impl Game {
             self.positions[idx] = position;
             self.behaviours[idx] = behaviour;
             self.frame_animators[idx] =
-                Some(self.frame_atlas.new_animator());
+                Some(self.assets.new_animator(self.frame_atlas)?);
             self.kinematics[idx] = Some(Kinematic::new());
-            self.are_player_friendly[idx] = true;
+            self.collisions[idx] = Collision::new(
+                &[CollisionLayer::Player, CollisionLayer::Hurtbox],
+                &[CollisionLayer::World],
+            );
+            self.attacks[idx] = Attack::new(&[CollisionLayer::Enemy]);
             self.healths[idx] = Some(Health::new(1000.0));
+            self.player_idx = Some(idx);
         }
+
+        Ok(())
     }
 
-    pub fn new_wolf_ai(&mut self, position: Vec2<f32>) {
-        if let Some(idx) = self.new_entity() {
+    pub fn new_wolf_ai(
+        &mut self,
+        position: Vec2<f32>,
+    ) -> Result<Option<usize>> {
+        let idx = self.new_entity();
+        if let Some(idx) = idx {
             let wolf_ai = WolfAI::new();
             let behaviour = Behaviour::WolfAIBehaviour(wolf_ai);
 
             self.positions[idx] = position;
             self.behaviours[idx] = behaviour;
             self.frame_animators[idx] =
-                Some(self.frame_atlas.new_animator());
+                Some(self.assets.new_animator(self.frame_atlas)?);
             self.kinematics[idx] = Some(Kinematic::new());
+            self.collisions[idx] = Collision::new(
+                &[CollisionLayer::Enemy, CollisionLayer::Hurtbox],
+                &[CollisionLayer::World],
+            );
+            self.attacks[idx] = Attack::new(&[CollisionLayer::Player]);
             self.healths[idx] = Some(Health::new(1000.0));
         }
+
+        Ok(idx)
     }
 
     pub fn new_rigid_collider(&mut self, pivot: Pivot, size: Vec2<f32>) {
         if let Some(idx) = self.new_entity() {
             let rect = Rect::from_pivot(pivot, size);
             self.rigid_colliders[idx] = Some(rect);
+            self.collisions[idx] =
+                Collision::new(&[CollisionLayer::World], &[]);
         }
     }
+
+    /// Trigger collider is centered at the entity position, so
+    /// spawners can use it as the spawn point.
+    fn new_trigger(&mut self, rect: Rect, action: TriggerAction) {
+        if let Some(idx) = self.new_entity() {
+            let position = rect.get_center();
+            let collider = rect.translate(position.scale(-1.0));
+
+            self.positions[idx] = position;
+            self.triggers[idx] = Some(Trigger::new(collider, action));
+            self.collisions[idx] = Collision::new(
+                &[CollisionLayer::Trigger],
+                &[CollisionLayer::Player],
+            );
+        }
+    }
+}
+
+/// Entities which own one another (e.g. a projectile and the one who
+/// shot it, or a spawner and the spawned enemy) never collide or attack
+/// each other.
+fn check_if_related(
+    owners: &[Option<usize>],
+    idx: usize,
+    other_idx: usize,
+) -> bool {
+    owners[idx] == Some(other_idx) || owners[other_idx] == Some(idx)
+}
+
+/// Entities, whose rigid colliders overlap the collider of the trigger
+/// entity `idx`. The slices hold only the alive entities.
+fn get_overlapped_entity_ids(
+    idx: usize,
+    collider: Rect,
+    positions: &[Vec2<f32>],
+    collisions: &[Collision],
+    rigid_colliders: &[Option<Rect>],
+) -> Vec<usize> {
+    let mut overlapped_entity_ids = Vec::new();
+    for other_idx in 0..positions.len() {
+        if idx == other_idx
+            || !collisions[idx]
+                .check_if_interacts_with(collisions[other_idx])
+        {
+            continue;
+        }
+
+        if let Some(other_collider) = rigid_colliders[other_idx] {
+            let other_collider =
+                other_collider.translate(positions[other_idx]);
+            if collider.check_if_collides_with_rect(other_collider) {
+                overlapped_entity_ids.push(other_idx);
+            }
+        }
+    }
+
+    overlapped_entity_ids
+}
+
+/// Enter and Stay events of the currently overlapped entities and Exit
+/// events of the previously overlapped ones, which are not overlapped
+/// anymore (moved out or removed).
+fn get_trigger_events(
+    trigger_idx: usize,
+    prev_entity_ids: &[usize],
+    curr_entity_ids: &[usize],
+) -> Vec<TriggerEvent> {
+    let mut events = Vec::new();
+    for &entity_idx in curr_entity_ids {
+        let event_type = if prev_entity_ids.contains(&entity_idx) {
+            TriggerEventType::Stay
+        } else {
+            TriggerEventType::Enter
+        };
+        events.push(TriggerEvent {
+            trigger_idx,
+            entity_idx,
+            event_type,
+        });
+    }
+
+    for &entity_idx in prev_entity_ids {
+        if !curr_entity_ids.contains(&entity_idx) {
+            events.push(TriggerEvent {
+                trigger_idx,
+                entity_idx,
+                event_type: TriggerEventType::Exit,
+            });
+        }
+    }
+
+    events
+}
+
+/// Targets of the attack of the entity `idx` with the hitboxes which hit
+/// them. Piercing hitboxes hit all of their targets, non-piercing ones
+/// only the closest target. A target hit by both gets the stronger hit.
+fn get_attack_hits(
+    attack: &Attack,
+    idx: usize,
+    positions: &[Vec2<f32>],
+    owners: &[Option<usize>],
+    collisions: &[Collision],
+    healths: &[Option<Health>],
+    hurt_colliders: &[Vec<Rect>],
+) -> Vec<(usize, Hitbox)> {
+    let mut hits = Vec::new();
+    if attack.hitboxes.is_empty() {
+        return hits;
+    }
+
+    let position = positions[idx];
+    let get_dist =
+        |other_idx: usize| (positions[other_idx] - position).len();
+    let mut closest_hit: Option<(usize, Hitbox)> = None;
+    for other_idx in 0..positions.len() {
+        let other_collision = collisions[other_idx];
+        if idx == other_idx
+            || check_if_related(owners, idx, other_idx)
+            || healths[other_idx].is_none()
+            || !other_collision.check_if_on_layer(CollisionLayer::Hurtbox)
+            || !attack.collision.check_if_interacts_with(other_collision)
+        {
+            continue;
+        }
+
+        let get_hit = |is_piercing| {
+            attack.get_hit(
+                position,
+                other_idx,
+                &hurt_colliders[other_idx],
+                positions[other_idx],
+                is_piercing,
+            )
+        };
+        if let Some(hitbox) = get_hit(true) {
+            hits.push((other_idx, hitbox));
+        }
+        if let Some(hitbox) = get_hit(false) {
+            if closest_hit
+                .is_none_or(|(idx, _)| get_dist(other_idx) < get_dist(idx))
+            {
+                closest_hit = Some((other_idx, hitbox));
+            }
+        }
+    }
+
+    if let Some((other_idx, hitbox)) = closest_hit {
+        match hits.iter_mut().find(|(idx, _)| *idx == other_idx) {
+            Some((_, hit)) if hit.damage < hitbox.damage => *hit = hitbox,
+            Some(_) => {}
+            None => hits.push((other_idx, hitbox)),
+        }
+    }
+
+    hits
+}
+
+/// Motion state of the entity, which its behaviour drives. The `dt` is
+/// the time step of the entity, it's zero during the hit-stop. The
+/// knockback comes through the velocity of the `kinematic`.
+struct Motion<'a> {
+    dt: f32,
+    position: &'a mut Vec2<f32>,
+    kinematic: &'a mut Kinematic,
+    look_dir: &'a mut f32,
 }
 
 fn update_knight_player(
     knight: &mut KnightPlayer,
     input: &mut Input,
-    dt: f32,
-    position: &mut Vec2<f32>,
-    kinematic: &mut Kinematic,
+    motion: Motion,
+    atlas: &FrameAtlas,
     animator: &mut FrameAnimator,
     damage: &mut f32,
-    look_dir: &mut f32,
-) {
+) -> Result<()> {
     use sdl2::keyboard::Keycode::*;
     use KnightPlayerState::*;
 
+    let Motion {
+        dt,
+        position,
+        kinematic,
+        look_dir,
+    } = motion;
+
     let is_attack_action = input.key_is_pressed(Space);
     let is_left_action = input.key_is_down(A);
     let is_right_action = input.key_is_down(D);
@@ -597,80 +1545,86 @@ fn update_knight_player(
     let is_step_action = is_right_action || is_left_action;
     let dir = if is_right_action { 1.0 } else { -1.0 };
 
-    if animator.is_finished() {
-        knight.curr_state = knight.next_state;
-        knight.next_state = Idle;
+    // The animator switches to the queued animations by itself, so the
+    // state follows the currently playing animation
+    let state = animator.get_animation_id().and_then(|id| {
+        KnightPlayerState::from_animation_name(
+            atlas.get_animation_name(id),
+        )
+    });
+    match state {
+        Some(state) if state != knight.curr_state => {
+            knight.curr_state = state;
+            knight.can_perform_combo = true;
+            knight.is_combo_window_open = false;
+        }
+        Some(_) => {}
+        None => Idle.play(atlas, animator)?,
+    }
+
+    if animator.check_if_event_crossed(atlas, "combo_window_open") {
+        knight.is_combo_window_open = true;
     }
 
+    let mut next_state = None;
     match knight.curr_state {
         Idle => {
             if is_attack_action {
-                knight.curr_state = Attack0;
-                knight.next_state = Idle;
-                knight.can_perform_combo = true;
+                next_state = Some(Attack0);
             } else if is_jump_action {
-                knight.curr_state = JumpUp;
+                next_state = Some(JumpUp);
                 kinematic.velocity.y += knight.jump_speed;
             } else if is_step_action {
-                knight.curr_state = Run;
+                next_state = Some(Run);
             }
         }
-        Attack0 => {
-            *damage = knight.attack0_damage;
-            knight.is_attack2_step_done = false;
-            if is_attack_action && knight.can_perform_combo {
-                if animator.progress > 0.7 {
-                    knight.next_state = Attack1;
+        Attack0 | Attack1 => {
+            let (attack_damage, combo_state) =
+                if knight.curr_state == Attack0 {
+                    (knight.attack0_damage, Attack1)
                 } else {
-                    knight.can_perform_combo = false;
-                }
-            }
-        }
-        Attack1 => {
-            *damage = knight.attack1_damage;
+                    (knight.attack1_damage, Attack2)
+                };
+
+            *damage = attack_damage;
             if is_attack_action && knight.can_perform_combo {
-                if animator.progress > 0.7 {
-                    knight.next_state = Attack2;
-                } else {
-                    knight.can_perform_combo = false;
+                knight.can_perform_combo = false;
+                if knight.is_combo_window_open {
+                    animator.clear_queue();
+                    combo_state.queue(atlas, animator)?;
+                    Idle.queue(atlas, animator)?;
                 }
             }
         }
         Attack2 => {
             *damage = knight.attack2_damage;
-            knight.next_state = Idle;
-            if !knight.is_attack2_step_done {
+            if animator.check_if_event_crossed(atlas, "step_forward") {
                 position.x += *look_dir * knight.attack2_step;
-                knight.is_attack2_step_done = true;
             }
         }
         Run => {
             if is_roll_action {
-                knight.curr_state = Roll;
+                next_state = Some(Roll);
             } else if is_jump_action {
-                knight.curr_state = JumpUp;
+                next_state = Some(JumpUp);
                 kinematic.velocity.y += knight.jump_speed;
             } else if is_attack_action {
-                knight.curr_state = Attack0;
-                knight.can_perform_combo = true;
+                next_state = Some(Attack0);
             } else if is_step_action {
                 position.x += dir * dt * knight.run_speed;
                 *look_dir = dir;
             } else {
-                knight.curr_state = Idle;
+                next_state = Some(Idle);
             }
         }
         Roll => {
-            knight.next_state = Idle;
             let speed =
                 knight.roll_speed * (1.0 - animator.progress.powf(2.0));
             position.x += *look_dir * dt * speed;
         }
         JumpUp => {
-            if kinematic.velocity.y > 0.0 {
-                knight.next_state = JumpUp;
-            } else {
-                knight.curr_state = JumpDown;
+            if kinematic.velocity.y <= 0.0 {
+                next_state = Some(JumpDown);
             }
 
             if is_step_action {
@@ -679,10 +1633,8 @@ fn update_knight_player(
             }
         }
         JumpDown => {
-            if !kinematic.is_grounded {
-                knight.next_state = JumpDown;
-            } else {
-                knight.curr_state = JumpLanding;
+            if kinematic.is_grounded {
+                next_state = Some(JumpLanding);
             }
 
             if is_step_action {
@@ -691,7 +1643,6 @@ fn update_knight_player(
             }
         }
         JumpLanding => {
-            knight.next_state = Idle;
             if is_step_action {
                 position.x += dir * dt * knight.landing_speed;
                 *look_dir = dir;
@@ -699,29 +1650,31 @@ fn update_knight_player(
         }
     }
 
-    match knight.curr_state {
-        Idle => animator.play("knight_idle", 0.07, true),
-        Run => animator.play("knight_run", 0.07, true),
-        Roll => animator.play("knight_roll", 0.07, false),
-        JumpUp => animator.play("knight_jump_up", 0.07, false),
-        JumpDown => animator.play("knight_jump_down", 0.07, false),
-        JumpLanding => animator.play("knight_jump_landing", 0.07, false),
-        Attack0 => animator.play("knight_attack_0", 0.07, false),
-        Attack1 => animator.play("knight_attack_1", 0.07, false),
-        Attack2 => animator.play("knight_attack_2", 0.07, false),
+    if let Some(state) = next_state {
+        knight.curr_state = state;
+        knight.can_perform_combo = true;
+        knight.is_combo_window_open = false;
+        state.play(atlas, animator)?;
     }
+
+    Ok(())
 }
 
 fn update_wolf_ai(
     wolf: &mut WolfAI,
     kinematic: &mut Kinematic,
+    atlas: &FrameAtlas,
     animator: &mut FrameAnimator,
     look_dir: &mut f32,
-) {
+) -> Result<()> {
     use WolfAIState::*;
 
-    if animator.is_finished() {
-        wolf.curr_state = wolf.next_state;
+    let state = animator.get_animation_id().and_then(|id| {
+        WolfAIState::from_animation_name(atlas.get_animation_name(id))
+    });
+    match state {
+        Some(state) => wolf.curr_state = state,
+        None => Idle.play(atlas, animator)?,
     }
 
     match wolf.curr_state {
@@ -729,9 +1682,245 @@ fn update_wolf_ai(
         _ => {}
     }
 
-    match wolf.curr_state {
-        Idle => animator.play("wolf_idle", 0.07, true),
-        Run => animator.play("wolf_run", 0.07, true),
-        Attack => animator.play("wolf_attack", 0.07, false),
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_hitbox() -> Hitbox {
+        Hitbox {
+            collider: Rect::from_top_left(
+                Vec2::new(0.0, 1.0),
+                Vec2::new(1.0, 1.0),
+            ),
+            damage: 1.0,
+            knockback: Vec2::zeros(),
+            hit_stop: 0.0,
+            is_global_hit_stop: false,
+            rehit_interval: None,
+            is_piercing: true,
+        }
+    }
+
+    fn get_hit(attack: &Attack, target_idx: usize) -> Option<Hitbox> {
+        let hurtbox =
+            Rect::from_top_left(Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0));
+
+        attack.get_hit(
+            Vec2::zeros(),
+            target_idx,
+            &[hurtbox],
+            Vec2::zeros(),
+            true,
+        )
+    }
+
+    /// Attacker at `attacker_idx` and enemies with the hurtboxes at the
+    /// positions.
+    fn get_hits(
+        attack: &Attack,
+        attacker_idx: usize,
+        positions: &[Vec2<f32>],
+        owners: &[Option<usize>],
+    ) -> Vec<(usize, Hitbox)> {
+        let n = positions.len();
+        let mut collisions = vec![
+            Collision::new(
+                &[CollisionLayer::Enemy, CollisionLayer::Hurtbox],
+                &[]
+            );
+            n
+        ];
+        collisions[attacker_idx] =
+            Collision::new(&[CollisionLayer::Player], &[]);
+        let hurtbox =
+            Rect::from_top_left(Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0));
+
+        get_attack_hits(
+            attack,
+            attacker_idx,
+            positions,
+            owners,
+            &collisions,
+            &vec![Some(Health::new(1.0)); n],
+            &vec![vec![hurtbox]; n],
+        )
+    }
+
+    /// Updates the trigger 0 at the origin, which is overlapped by the
+    /// first `n_entities` entities at the x positions. Returns the event
+    /// types of the entity 1.
+    fn update_trigger(
+        overlapped_entity_ids: &mut Vec<usize>,
+        xs: &[f32],
+        n_entities: usize,
+    ) -> Vec<TriggerEventType> {
+        let collisions = [
+            Collision::new(
+                &[CollisionLayer::Trigger],
+                &[CollisionLayer::Player],
+            ),
+            Collision::new(&[CollisionLayer::Player], &[]),
+            Collision::new(&[CollisionLayer::Player], &[]),
+        ];
+        let positions: Vec<_> =
+            xs.iter().map(|x| Vec2::new(*x, 0.0)).collect();
+        let rigid_collider =
+            Rect::from_center(Vec2::zeros(), Vec2::new(1.0, 1.0));
+        let collider =
+            Rect::from_center(Vec2::zeros(), Vec2::new(2.0, 2.0));
+
+        let curr_entity_ids = get_overlapped_entity_ids(
+            0,
+            collider,
+            &positions[..n_entities],
+            &collisions[..n_entities],
+            &[Some(rigid_collider); 3][..n_entities],
+        );
+        let events =
+            get_trigger_events(0, overlapped_entity_ids, &curr_entity_ids);
+        *overlapped_entity_ids = curr_entity_ids;
+
+        events
+            .iter()
+            .filter(|event| event.entity_idx == 1)
+            .map(|event| event.event_type)
+            .collect()
+    }
+
+    #[test]
+    fn test_trigger_enter_stay_exit() {
+        use TriggerEventType::*;
+
+        let mut overlapped_entity_ids = Vec::new();
+        let mut event_types = Vec::new();
+        for x in [5.0, 0.0, 0.5, 5.0, 5.0, 0.0] {
+            event_types.push(update_trigger(
+                &mut overlapped_entity_ids,
+                &[0.0, x, 0.0],
+                3,
+            ));
+        }
+
+        assert_eq!(
+            event_types,
+            [
+                vec![],
+                vec![Enter],
+                vec![Stay],
+                vec![Exit],
+                vec![],
+                vec![Enter]
+            ]
+        );
+        assert_eq!(overlapped_entity_ids, [1, 2]);
+    }
+
+    #[test]
+    fn test_trigger_exit_of_removed_entity() {
+        let mut overlapped_entity_ids = Vec::new();
+        let xs = [0.0, 0.0, 0.0];
+        update_trigger(&mut overlapped_entity_ids, &xs, 3);
+
+        // The entities 1 and 2 are removed while inside the trigger
+        let event_types =
+            update_trigger(&mut overlapped_entity_ids, &xs, 1);
+        assert_eq!(event_types, [TriggerEventType::Exit]);
+        assert!(overlapped_entity_ids.is_empty());
+    }
+
+    #[test]
+    fn test_owned_attack_does_not_hit_owner() {
+        let mut attack = Attack::new(&[CollisionLayer::Enemy]);
+        attack.update(1, vec![new_hitbox()]);
+        let positions = [Vec2::zeros(); 3];
+
+        let hits =
+            get_hits(&attack, 1, &positions, &[None, Some(0), None]);
+        let targets: Vec<_> = hits.iter().map(|(idx, _)| *idx).collect();
+        assert_eq!(targets, [2]);
+
+        let hits = get_hits(&attack, 1, &positions, &[None; 3]);
+        let targets: Vec<_> = hits.iter().map(|(idx, _)| *idx).collect();
+        assert_eq!(targets, [0, 2]);
+    }
+
+    #[test]
+    fn test_piercing_hits_all_and_non_piercing_hits_closest() {
+        let mut non_piercing = new_hitbox();
+        non_piercing.is_piercing = false;
+        non_piercing.damage = 5.0;
+        let mut attack = Attack::new(&[CollisionLayer::Enemy]);
+        attack.update(1, vec![new_hitbox(), non_piercing]);
+        let positions =
+            [Vec2::zeros(), Vec2::new(0.5, 0.0), Vec2::new(0.25, 0.0)];
+
+        let mut hits = get_hits(&attack, 0, &positions, &[None; 3]);
+        hits.sort_by_key(|(idx, _)| *idx);
+        let hits: Vec<_> =
+            hits.iter().map(|(idx, hit)| (*idx, hit.damage)).collect();
+        assert_eq!(hits, [(1, 1.0), (2, 5.0)]);
+    }
+
+    #[test]
+    fn test_non_piercing_hitbox_sticks_to_first_target() {
+        let mut hitbox = new_hitbox();
+        hitbox.is_piercing = false;
+        hitbox.rehit_interval = Some(0.0);
+        let mut attack = Attack::new(&[CollisionLayer::Enemy]);
+        attack.update(1, vec![new_hitbox(), hitbox]);
+
+        // Piercing hits don't make the non-piercing hitbox stick
+        attack.attack_entity(1, &new_hitbox());
+        attack.attack_entity(0, &hitbox);
+        attack.attack_entity(1, &new_hitbox());
+        assert!(attack.check_if_can_hit(0, &hitbox));
+        assert!(!attack.check_if_can_hit(1, &hitbox));
+    }
+
+    #[test]
+    fn test_attack_hits_once_per_animation_id() {
+        let mut attack = Attack::new(&[CollisionLayer::Enemy]);
+        attack.update(1, vec![new_hitbox()]);
+        assert!(get_hit(&attack, 0).is_some());
+
+        attack.attack_entity(0, &new_hitbox());
+        attack.update(1, vec![new_hitbox()]);
+        assert!(get_hit(&attack, 0).is_none());
+        assert!(get_hit(&attack, 1).is_some());
+    }
+
+    #[test]
+    fn test_attack_resets_on_new_animation_id() {
+        let mut attack = Attack::new(&[CollisionLayer::Enemy]);
+        attack.update(1, vec![new_hitbox()]);
+        attack.attack_entity(0, &new_hitbox());
+
+        attack.update(2, vec![new_hitbox()]);
+        assert!(get_hit(&attack, 0).is_some());
+    }
+
+    #[test]
+    fn test_attack_resets_on_animator_restart() {
+        let meta = r#"{"size": [1, 1], "frames": {"a": [{"sprite": {"x": 0, "y": 0, "w": 1, "h": 1}, "masks": {}}]}}"#;
+        let mut assets = Assets::new();
+        let handle =
+            assets.insert_atlas(serde_json::from_str(meta).unwrap());
+        let atlas = assets.get_atlas(handle).unwrap();
+        let id = atlas.get_animation_id("a").unwrap();
+        let mut animator = assets.new_animator(handle).unwrap();
+        let mut attack = Attack::new(&[CollisionLayer::Enemy]);
+
+        animator.play(id, 0.1, PlayMode::Loop);
+        attack.update(animator.update(atlas, 0.1).0, vec![new_hitbox()]);
+        attack.attack_entity(0, &new_hitbox());
+        attack.update(animator.update(atlas, 0.1).0, vec![new_hitbox()]);
+        assert!(get_hit(&attack, 0).is_none());
+
+        animator.play(id, 0.1, PlayMode::Once);
+        attack.update(animator.update(atlas, 0.1).0, vec![new_hitbox()]);
+        assert!(get_hit(&attack, 0).is_some());
     }
 }
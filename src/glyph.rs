@@ -0,0 +1,103 @@
+use crate::error::{Error, Result};
+use crate::frame::XYWH;
+use crate::vec::*;
+use fontdue::{Font, FontSettings};
+use std::fs;
+
+const FIRST_CHAR: u8 = 32;
+const LAST_CHAR: u8 = 126;
+const ATLAS_WIDTH: usize = 512;
+const GLYPH_PADDING: usize = 1;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Glyph {
+    pub xywh: XYWH,
+    pub offset: Vec2<f32>,
+    pub advance: f32,
+}
+
+/// Single channel atlas of the printable ascii glyphs. As the sprite
+/// atlas, the image rows go from the bottom to the top, so the glyphs
+/// xywh is given in the OpenGL texture coordinates.
+pub struct GlyphAtlas {
+    pub size: [u32; 2],
+    pub image: Vec<u8>,
+    pub font_size: f32,
+    glyphs: Vec<Glyph>,
+}
+
+impl GlyphAtlas {
+    pub fn new(font_fp: &str, font_size: f32) -> Result<Self> {
+        let bytes = fs::read(font_fp).map_err(Error::io(font_fp))?;
+        let font = Font::from_bytes(bytes, FontSettings::default())
+            .map_err(|message| Error::Font {
+                fp: font_fp.to_string(),
+                message,
+            })?;
+
+        // Pack glyph bitmaps in rows, y goes down while packing
+        let mut bitmaps = Vec::new();
+        let mut positions = Vec::new();
+        let mut cursor = Vec2::new(0, 0);
+        let mut row_height = 0;
+        for c in FIRST_CHAR..=LAST_CHAR {
+            let (metrics, bitmap) = font.rasterize(c as char, font_size);
+            if cursor.x + metrics.width + GLYPH_PADDING > ATLAS_WIDTH {
+                cursor =
+                    Vec2::new(0, cursor.y + row_height + GLYPH_PADDING);
+                row_height = 0;
+            }
+
+            positions.push(cursor);
+            cursor.x += metrics.width + GLYPH_PADDING;
+            row_height = row_height.max(metrics.height);
+            bitmaps.push((metrics, bitmap));
+        }
+
+        let height = cursor.y + row_height;
+        let mut image = vec![0; ATLAS_WIDTH * height];
+        let mut glyphs = Vec::with_capacity(bitmaps.len());
+        for ((metrics, bitmap), position) in bitmaps.iter().zip(positions)
+        {
+            for y in 0..metrics.height {
+                let row = height - 1 - (position.y + y);
+                let src =
+                    &bitmap[y * metrics.width..(y + 1) * metrics.width];
+                let start = row * ATLAS_WIDTH + position.x;
+                image[start..start + metrics.width].copy_from_slice(src);
+            }
+
+            glyphs.push(Glyph {
+                xywh: XYWH {
+                    x: position.x as u32,
+                    y: (height - position.y) as u32,
+                    w: metrics.width as u32,
+                    h: metrics.height as u32,
+                },
+                offset: Vec2::new(
+                    metrics.xmin as f32,
+                    metrics.ymin as f32,
+                ),
+                advance: metrics.advance_width,
+            });
+        }
+
+        Ok(Self {
+            size: [ATLAS_WIDTH as u32, height as u32],
+            image,
+            font_size,
+            glyphs,
+        })
+    }
+
+    /// Unknown characters fall back to the question mark.
+    pub fn get_glyph(&self, c: char) -> &Glyph {
+        let c = if (FIRST_CHAR as char..=LAST_CHAR as char).contains(&c) {
+            c
+        } else {
+            '?'
+        };
+
+        &self.glyphs[(c as u8 - FIRST_CHAR) as usize]
+    }
+}
@@ -0,0 +1,179 @@
+//! Golden image tests of the renderer. Each scene is rendered by the
+//! headless renderer and compared with its image in `tests/golden`.
+//! On a mismatch the actual and the diff images are written to
+//! `target/golden`. Run with `BLESS=1` to overwrite the golden images
+//! after an intended change of the rendering.
+use crate::frame::{FrameAtlas, FrameTransform, XYWH};
+use crate::renderer::*;
+use crate::vec::*;
+use image::{Rgba, RgbaImage};
+use std::env;
+use std::fs;
+
+const GOLDEN_DIR: &str = "./tests/golden";
+const OUTPUT_DIR: &str = "./target/golden";
+const FRAME_ATLAS_META_FP: &str = "./assets/sprites/atlas.json";
+const FRAME_ATLAS_IMAGE_FP: &str = "./assets/sprites/atlas.png";
+
+/// Max difference of the pixel channel, which is still a match. GL
+/// implementations round the colors and the rasterized edges a bit
+/// differently.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Max share of the mismatched pixels.
+const MAX_MISMATCH_RATIO: f32 = 0.002;
+
+#[test]
+fn test_golden_sprite_flips() {
+    let flips = [(false, false), (true, false), (false, true)];
+    check_scene("sprite_flips", Vec2::new(120.0, 40.0), |renderer| {
+        let (texture, sprite) = load_knight_sprite(renderer);
+        for (i, (flip_x, flip_y)) in flips.into_iter().enumerate() {
+            let x = -40.0 + 40.0 * i as f32;
+            let transform = FrameTransform {
+                flip_x,
+                flip_y,
+                rotation: 0.0,
+            };
+            renderer.push_primitive(DrawPrimitive::world_sprite(
+                texture,
+                sprite,
+                Pivot::Center(Vec2::new(x, 0.0)),
+                false,
+                transform,
+            ));
+        }
+    });
+}
+
+#[test]
+fn test_golden_lit_sprite() {
+    check_scene("lit_sprite", Vec2::new(40.0, 40.0), |renderer| {
+        let (texture, sprite) = load_knight_sprite(renderer);
+        renderer.push_primitive(DrawPrimitive::world_sprite(
+            texture,
+            sprite,
+            Pivot::Center(Vec2::zeros()),
+            true,
+            FrameTransform::default(),
+        ));
+        renderer.push_light(DrawLight::new(
+            Vec2::new(-15.0, 10.0),
+            Color::new(1.0, 0.7, 0.4, 1.0),
+            [1.0, 0.02, 0.002],
+        ));
+    });
+}
+
+#[test]
+fn test_golden_stone_wall() {
+    check_scene("stone_wall", Vec2::new(100.0, 60.0), |renderer| {
+        let rect = Rect::from_center(Vec2::zeros(), Vec2::new(80.0, 40.0));
+        let mut primitive =
+            DrawPrimitive::world_rect(rect, Color::gray(1.0, 1.0));
+        primitive.effect = EffectType::StoneWallEffect as u32;
+        renderer.push_primitive(primitive);
+    });
+}
+
+#[test]
+fn test_golden_text() {
+    check_scene("text", Vec2::new(80.0, 20.0), |renderer| {
+        renderer.push_text(
+            "Lift 0123",
+            Pivot::Center(Vec2::new(80.0, 20.0)),
+            SpaceType::ScreenSpace,
+            RenderLayer::UI,
+            24.0,
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        );
+    });
+}
+
+fn load_knight_sprite(renderer: &mut Renderer) -> (TextureHandle, XYWH) {
+    let atlas = FrameAtlas::new(FRAME_ATLAS_META_FP).unwrap();
+    let id = atlas.get_animation_id("knight_idle").unwrap();
+    let sprite = atlas.get_frames(id)[0].sprite;
+    let texture = renderer.load_texture(FRAME_ATLAS_IMAGE_FP).unwrap();
+
+    (texture, sprite)
+}
+
+/// Renders the scene with the camera at the origin. The image is twice
+/// as large as the camera view, so each world unit is 2x2 pixels.
+fn check_scene(
+    name: &str,
+    view_size: Vec2<f32>,
+    push_scene: impl FnOnce(&mut Renderer),
+) {
+    let size = Vec2::new(view_size.x as u32 * 2, view_size.y as u32 * 2);
+    with_headless_renderer(size, |renderer| {
+        renderer.set_camera(Vec2::zeros(), view_size);
+        push_scene(renderer);
+
+        let target = renderer.create_render_target(size).unwrap();
+        renderer.render_to_target(target);
+        let image = renderer.read_pixels(target);
+
+        compare_with_golden(name, &image);
+    });
+}
+
+fn compare_with_golden(name: &str, image: &RgbaImage) {
+    let golden_fp = format!("{}/{}.png", GOLDEN_DIR, name);
+    if env::var_os("BLESS").is_some() {
+        fs::create_dir_all(GOLDEN_DIR).unwrap();
+        image.save(&golden_fp).unwrap();
+        return;
+    }
+
+    let golden = image::open(&golden_fp)
+        .unwrap_or_else(|e| {
+            panic!(
+                "Can't load golden image {}: {}. Run with BLESS=1 to \
+                 create it",
+                golden_fp, e
+            )
+        })
+        .to_rgba8();
+    assert_eq!(
+        image.dimensions(),
+        golden.dimensions(),
+        "{} size differs from the golden image",
+        name
+    );
+
+    // Mismatched pixels are red on top of the dimmed golden image
+    let mut diff = RgbaImage::new(image.width(), image.height());
+    let mut n_mismatches = 0;
+    for (x, y, actual) in image.enumerate_pixels() {
+        let expected = golden.get_pixel(x, y);
+        let is_match = actual
+            .0
+            .iter()
+            .zip(expected.0)
+            .all(|(a, e)| a.abs_diff(e) <= CHANNEL_TOLERANCE);
+
+        let pixel = if is_match {
+            let [r, g, b, _] = expected.0;
+            Rgba([r / 4, g / 4, b / 4, 255])
+        } else {
+            n_mismatches += 1;
+            Rgba([255, 0, 0, 255])
+        };
+        diff.put_pixel(x, y, pixel);
+    }
+
+    let n_pixels = image.width() * image.height();
+    if n_mismatches as f32 > MAX_MISMATCH_RATIO * n_pixels as f32 {
+        fs::create_dir_all(OUTPUT_DIR).unwrap();
+        let actual_fp = format!("{}/{}.png", OUTPUT_DIR, name);
+        let diff_fp = format!("{}/{}_diff.png", OUTPUT_DIR, name);
+        image.save(&actual_fp).unwrap();
+        diff.save(&diff_fp).unwrap();
+
+        panic!(
+            "{}: {} of {} pixels differ from {}, see {} and {}",
+            name, n_mismatches, n_pixels, golden_fp, actual_fp, diff_fp
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::{Error, Result};
+use crate::vec::*;
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Deserialize)]
+struct TiledObject {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+#[derive(Deserialize)]
+struct TiledLayer {
+    #[serde(default)]
+    objects: Vec<TiledObject>,
+}
+
+#[derive(Deserialize)]
+struct TiledMap {
+    height: u32,
+    tileheight: u32,
+    layers: Vec<TiledLayer>,
+}
+
+pub struct LevelObject {
+    pub name: String,
+    pub kind: String,
+    pub rect: Rect,
+}
+
+pub struct Level {
+    pub objects: Vec<LevelObject>,
+}
+
+impl Level {
+    pub fn new(file_path: &str) -> Result<Self> {
+        let meta =
+            fs::read_to_string(file_path).map_err(Error::io(file_path))?;
+        let map: TiledMap =
+            serde_json::from_str(&meta).map_err(Error::json(file_path))?;
+
+        // NOTE: Tiled keeps the y axis pointing down from the top of the
+        // map, while the world y axis points up from the bottom
+        let map_height = (map.height * map.tileheight) as f32;
+        let mut objects = Vec::new();
+        for object in map.layers.into_iter().flat_map(|l| l.objects) {
+            let top_left = Vec2::new(object.x, map_height - object.y);
+            let size = Vec2::new(object.width, object.height);
+
+            objects.push(LevelObject {
+                name: object.name,
+                kind: object.kind,
+                rect: Rect::from_top_left(top_left, size),
+            });
+        }
+
+        Ok(Self { objects })
+    }
+}
@@ -2,22 +2,49 @@
 #![allow(unused_variables)]
 
 use game::*;
+use std::env;
+use std::process;
 use vec::Vec2;
 
+mod assets;
+mod debug_draw;
+mod error;
 mod frame;
 mod game;
+mod glyph;
+#[cfg(test)]
+mod golden_tests;
 mod input;
+mod level;
 mod renderer;
+mod shader;
 mod utils;
 mod vec;
+mod watcher;
 
 fn main() {
     let window_size: Vec2<u32> = Vec2::new(1500, 800);
 
-    let game = Box::leak(Box::new(Game::new(
+    // Compiles the shaders without a window and exits
+    if env::args().any(|arg| arg == "--check-shaders") {
+        if let Err(error) = renderer::check_shaders() {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+        println!("Shaders are valid");
+        return;
+    }
+
+    let result = Game::new(
         window_size,
         "./assets/sprites/atlas.json",
         "./assets/sprites/atlas.png",
-    )));
-    game.start();
+        &["./assets/levels/0.json", "./assets/levels/1.json"],
+    )
+    .and_then(|mut game| game.start());
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
 }
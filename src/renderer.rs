@@ -1,25 +1,43 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-use crate::frame::XYWH;
+use crate::error::{Error, Result};
+use crate::frame::{FrameTransform, Insets, XYWH};
+use crate::glyph::GlyphAtlas;
+use crate::shader::ShaderSource;
 use crate::vec::*;
 use core::fmt::Debug;
 use enum_iterator::{all, Sequence};
 use glow::HasContext;
 use image::imageops::flip_vertical_in_place;
 use image::io::Reader as ImageReader;
-use std::fs;
-use std::mem::size_of;
+use image::{Rgba, RgbaImage};
+use std::mem::{offset_of, size_of};
+use std::path::Path;
 
 const MAX_N_INSTANCED_PRIMITIVES: usize = 1 << 12;
 const MAX_N_LIGHTS: usize = 32;
+const MAX_N_BOUND_TEXTURE_PAGES: usize = 8;
 const COMMON_GLSL_SHADER_FP: &str = "./assets/shaders/common.glsl";
+const NOISE_GLSL_SHADER_FP: &str = "./assets/shaders/noise.glsl";
+const POISSON_GLSL_SHADER_FP: &str = "./assets/shaders/poisson.glsl";
 const PRIMITIVE_VERT_SHADER_FP: &str = "./assets/shaders/primitive.vert";
 const PRIMITIVE_FRAG_SHADER_FP: &str = "./assets/shaders/primitive.frag";
 const SCREEN_RECT_VERT_SHADER_FP: &str =
     "./assets/shaders/screen_rect.vert";
 const HDR_RESOLVE_FRAG_SHADER_FP: &str =
     "./assets/shaders/hdr_resolve.frag";
+pub const SHADER_FPS: [&str; 7] = [
+    COMMON_GLSL_SHADER_FP,
+    NOISE_GLSL_SHADER_FP,
+    POISSON_GLSL_SHADER_FP,
+    PRIMITIVE_VERT_SHADER_FP,
+    PRIMITIVE_FRAG_SHADER_FP,
+    SCREEN_RECT_VERT_SHADER_FP,
+    HDR_RESOLVE_FRAG_SHADER_FP,
+];
+const GLYPH_ATLAS_FONT_FP: &str = "./assets/fonts/Montserrat-Bold.ttf";
+const GLYPH_ATLAS_FONT_SIZE: f32 = 32.0;
 
 pub struct Renderer {
     window: sdl2::video::Window,
@@ -29,29 +47,42 @@ pub struct Renderer {
     // Primitive renderer
     primitive_program: glow::NativeProgram,
     primitive_vao: glow::NativeVertexArray,
-    a_xywh: Attribute<f32>,
-    a_space: Attribute<u32>,
-    a_effect: Attribute<u32>,
-    a_tex_uvwh: Attribute<f32>,
-    a_rgba: Attribute<f32>,
-    a_tex_id: Attribute<u32>,
-    a_flip: Attribute<f32>,
+    primitive_instances: InstanceBuffer<PrimitiveInstance>,
 
     // HDR resolve renderer
     hdr_resolve_program: glow::NativeProgram,
-    hdr_buffer_size: Vec2<u32>,
-    hdr_fbo: glow::NativeFramebuffer,
-    hdr_tex: glow::Texture,
+    hdr_buffer: RenderTarget,
+    render_targets: Vec<Option<RenderTarget>>,
 
     // Resource textures
-    sprite_atlas_tex: glow::Texture,
-    // glyph_atlas_tex: Option<glow::Texture>,
+    texture_pages: Vec<TexturePage>,
+    textures: Vec<TextureSlot>,
+    glyph_atlas_texture: TextureHandle,
+    glyph_atlas: GlyphAtlas,
 
     // World
     camera_position: Vec2<f32>,
     camera_view_size: Vec2<f32>,
     primitives: Vec<DrawPrimitive>,
     lights: Vec<DrawLight>,
+    batches: Vec<Batch>,
+    stats: RenderStats,
+}
+
+/// Range of the sorted primitives drawn by a single draw call with the
+/// texture pages bound to the first texture units.
+struct Batch {
+    start: usize,
+    count: usize,
+    pages: Vec<usize>,
+    blend: BlendMode,
+}
+
+/// Counters of the last rendered frame.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct RenderStats {
+    pub n_primitives: usize,
+    pub n_batches: usize,
 }
 
 impl Renderer {
@@ -59,24 +90,46 @@ impl Renderer {
         sdl: &sdl2::Sdl,
         window_name: &str,
         window_size: Vec2<u32>,
-        sprite_atlas_image_fp: &str,
-    ) -> Self {
-        // ---------------------------------------------------------------
-        // Initialize gl and window
-        let video = sdl.video().unwrap();
+    ) -> Result<Self> {
+        let video = sdl.video().map_err(Error::Sdl)?;
+        set_gl_attributes(&video);
 
         let window = video
             .window(window_name, window_size.x, window_size.y)
             .opengl()
             .resizable()
             .build()
-            .unwrap();
+            .map_err(|e| Error::Sdl(e.to_string()))?;
+
+        Self::from_window(&video, window)
+    }
 
-        let gl_attr = video.gl_attr();
-        gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
-        gl_attr.set_context_version(4, 6);
+    /// Renderer with the hidden window, which draws only into the
+    /// render targets. With the `offscreen` SDL video driver
+    /// (`SDL_VIDEODRIVER=offscreen`) it doesn't need a display, e.g. on
+    /// the headless machine with the software OpenGL (Mesa llvmpipe).
+    pub fn new_headless(sdl: &sdl2::Sdl, size: Vec2<u32>) -> Result<Self> {
+        let video = sdl.video().map_err(Error::Sdl)?;
+        set_gl_attributes(&video);
 
-        let _gl_context = window.gl_create_context().unwrap();
+        let window = video
+            .window("", size.x, size.y)
+            .opengl()
+            .hidden()
+            .build()
+            .map_err(|e| Error::Sdl(e.to_string()))?;
+
+        Self::from_window(&video, window)
+    }
+
+    fn from_window(
+        video: &sdl2::VideoSubsystem,
+        window: sdl2::video::Window,
+    ) -> Result<Self> {
+        // ---------------------------------------------------------------
+        // Initialize gl
+        let _gl_context =
+            window.gl_create_context().map_err(Error::Sdl)?;
         let gl: glow::Context;
         unsafe {
             gl = glow::Context::from_loader_function(|s| {
@@ -84,162 +137,264 @@ impl Renderer {
             });
         }
 
-        video.gl_set_swap_interval(1).unwrap();
+        video.gl_set_swap_interval(1).map_err(Error::Sdl)?;
 
         // ---------------------------------------------------------------
         // Initialize primitive renderer
         let primitive_program = create_program(
             &gl,
-            Some(COMMON_GLSL_SHADER_FP),
             PRIMITIVE_VERT_SHADER_FP,
             PRIMITIVE_FRAG_SHADER_FP,
-        );
-        let primitive_vao = create_vao(&gl);
+        )?;
+        let primitive_vao = create_vao(&gl)?;
         unsafe {
             gl.bind_vertex_array(Some(primitive_vao));
         }
 
-        let a_xywh = Attribute::new(
-            &gl,
-            primitive_program,
-            4,
-            "a_xywh",
-            glow::FLOAT,
-            MAX_N_INSTANCED_PRIMITIVES,
-            1,
-        );
-        let a_space = Attribute::new(
+        let primitive_instances = InstanceBuffer::new(
             &gl,
-            primitive_program,
-            1,
-            "a_space",
-            glow::UNSIGNED_INT,
+            &PRIMITIVE_INSTANCE_ATTRIBUTES,
             MAX_N_INSTANCED_PRIMITIVES,
-            1,
-        );
-        let a_effect = Attribute::new(
-            &gl,
-            primitive_program,
-            1,
-            "a_effect",
-            glow::UNSIGNED_INT,
-            MAX_N_INSTANCED_PRIMITIVES,
-            1,
-        );
-        let a_tex_uvwh = Attribute::new(
-            &gl,
-            primitive_program,
-            4,
-            "a_tex_uvwh",
-            glow::FLOAT,
-            MAX_N_INSTANCED_PRIMITIVES,
-            1,
-        );
-        let a_rgba = Attribute::new(
-            &gl,
-            primitive_program,
-            4,
-            "a_rgba",
-            glow::FLOAT,
-            MAX_N_INSTANCED_PRIMITIVES,
-            1,
-        );
-        let a_tex_id = Attribute::new(
-            &gl,
-            primitive_program,
-            1,
-            "a_tex_id",
-            glow::UNSIGNED_INT,
-            MAX_N_INSTANCED_PRIMITIVES,
-            1,
-        );
-        let a_flip = Attribute::new(
-            &gl,
-            primitive_program,
-            1,
-            "a_flip",
-            glow::FLOAT,
-            MAX_N_INSTANCED_PRIMITIVES,
-            1,
-        );
+        )?;
 
         // ---------------------------------------------------------------
-        // Initialize HDR resolve renderer
-        let hdr_buffer_size = window_size;
+        // Initialize HDR resolve renderer. The HDR buffer is resized to
+        // the frame it's resolved to
         let hdr_resolve_program = create_program(
             &gl,
-            Some(COMMON_GLSL_SHADER_FP),
             SCREEN_RECT_VERT_SHADER_FP,
             HDR_RESOLVE_FRAG_SHADER_FP,
-        );
-        let hdr_tex;
-        let hdr_fbo;
-        unsafe {
-            hdr_fbo = gl.create_framebuffer().unwrap();
-            hdr_tex = create_texture(
-                &gl,
-                glow::RGBA32F as i32,
-                hdr_buffer_size.x as i32,
-                hdr_buffer_size.y as i32,
-                glow::RGBA,
-                glow::FLOAT,
-                None,
-                glow::NEAREST,
-            );
-            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(hdr_fbo));
-            gl.framebuffer_texture_2d(
-                glow::FRAMEBUFFER,
-                glow::COLOR_ATTACHMENT0,
-                glow::TEXTURE_2D,
-                Some(hdr_tex),
-                0,
-            );
-            gl.draw_buffer(glow::COLOR_ATTACHMENT0);
-            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
-        }
-
-        // ---------------------------------------------------------------
-        // Initialize texture (sprites and glyphs)
-        let mut sprite_atlas_image =
-            ImageReader::open(sprite_atlas_image_fp)
-                .unwrap()
-                .decode()
-                .unwrap();
-        flip_vertical_in_place(&mut sprite_atlas_image);
-
-        let sprite_atlas_tex = create_texture(
+        )?;
+        let (width, height) = window.size();
+        let hdr_buffer = create_render_target(
             &gl,
-            glow::RGBA as i32,
-            sprite_atlas_image.width() as i32,
-            sprite_atlas_image.height() as i32,
-            glow::RGBA,
-            glow::UNSIGNED_BYTE,
-            Some(&sprite_atlas_image.as_bytes().to_vec()),
-            glow::LINEAR,
-        );
+            Vec2::new(width, height),
+            glow::RGBA32F,
+            glow::FLOAT,
+        )?;
 
-        Self {
+        let glyph_atlas =
+            GlyphAtlas::new(GLYPH_ATLAS_FONT_FP, GLYPH_ATLAS_FONT_SIZE)?;
+
+        let mut renderer = Self {
             window,
             gl,
             _gl_context,
             primitive_program,
             primitive_vao,
-            a_xywh,
-            a_space,
-            a_effect,
-            a_tex_uvwh,
-            a_rgba,
-            a_tex_id,
-            a_flip,
+            primitive_instances,
             hdr_resolve_program,
-            hdr_buffer_size,
-            hdr_fbo,
-            hdr_tex,
-            sprite_atlas_tex,
+            hdr_buffer,
+            render_targets: Vec::new(),
+            texture_pages: Vec::new(),
+            textures: Vec::new(),
+            glyph_atlas_texture: TextureHandle(0),
+            glyph_atlas,
             camera_position: Vec2::zeros(),
             camera_view_size: Vec2::zeros(),
             primitives: Vec::with_capacity(MAX_N_INSTANCED_PRIMITIVES),
             lights: Vec::with_capacity(MAX_N_LIGHTS),
+            batches: Vec::new(),
+            stats: RenderStats::default(),
+        };
+
+        // ---------------------------------------------------------------
+        // Initialize glyph texture (the glyph coverage goes to all
+        // channels, the shader reads the red one)
+        let glyph_image = RgbaImage::from_fn(
+            renderer.glyph_atlas.size[0],
+            renderer.glyph_atlas.size[1],
+            |x, y| {
+                let idx = y * renderer.glyph_atlas.size[0] + x;
+                Rgba([renderer.glyph_atlas.image[idx as usize]; 4])
+            },
+        );
+        renderer.glyph_atlas_texture =
+            renderer.insert_texture(&glyph_image)?;
+
+        Ok(renderer)
+    }
+
+    /// Recompiles the programs from the shader files. A program which
+    /// fails to compile is kept as is and the first error is returned.
+    pub fn reload_programs(&mut self) -> Result<()> {
+        let primitive_program = create_program(
+            &self.gl,
+            PRIMITIVE_VERT_SHADER_FP,
+            PRIMITIVE_FRAG_SHADER_FP,
+        );
+        let hdr_resolve_program = create_program(
+            &self.gl,
+            SCREEN_RECT_VERT_SHADER_FP,
+            HDR_RESOLVE_FRAG_SHADER_FP,
+        );
+
+        // NOTE: Attribute locations are fixed in the shaders, so the
+        // vertex array doesn't need to be re-created for the new program
+        unsafe {
+            if let Ok(program) = primitive_program {
+                self.gl.delete_program(self.primitive_program);
+                self.primitive_program = program;
+            }
+            if let Ok(program) = hdr_resolve_program {
+                self.gl.delete_program(self.hdr_resolve_program);
+                self.hdr_resolve_program = program;
+            }
+        }
+
+        primitive_program.and(hdr_resolve_program).map(|_| ())
+    }
+
+    pub fn load_texture(
+        &mut self,
+        image_fp: &str,
+    ) -> Result<TextureHandle> {
+        let image = read_texture_image(image_fp)?;
+
+        self.insert_texture(&image)
+    }
+
+    /// Replaces the texture, but keeps its handle valid. The texture
+    /// moves to another page if its size doesn't fit the old one.
+    pub fn reload_texture(
+        &mut self,
+        handle: TextureHandle,
+        image_fp: &str,
+    ) -> Result<()> {
+        let image = read_texture_image(image_fp)?;
+
+        self.replace_texture(handle, &image)
+    }
+
+    fn replace_texture(
+        &mut self,
+        handle: TextureHandle,
+        image: &RgbaImage,
+    ) -> Result<()> {
+        check_texture_image(image)?;
+        let slot = self.textures[handle.0];
+        let page_size = get_page_size(image);
+
+        if self.texture_pages[slot.page].size.to_array()
+            == page_size.to_array()
+        {
+            self.upload_texture_layer(slot.page, slot.layer, image);
+        } else {
+            let (page, layer) = self.allocate_texture_layer(page_size)?;
+            self.upload_texture_layer(page, layer, image);
+            self.textures[handle.0] = TextureSlot { page, layer };
+            self.texture_pages[slot.page].free_layers.push(slot.layer);
+        }
+
+        Ok(())
+    }
+
+    /// Textures are the layers of the texture arrays (pages). All
+    /// textures with the same power of two size share the page.
+    fn insert_texture(
+        &mut self,
+        image: &RgbaImage,
+    ) -> Result<TextureHandle> {
+        check_texture_image(image)?;
+        let (page, layer) =
+            self.allocate_texture_layer(get_page_size(image))?;
+        self.upload_texture_layer(page, layer, image);
+        self.textures.push(TextureSlot { page, layer });
+
+        Ok(TextureHandle(self.textures.len() - 1))
+    }
+
+    fn allocate_texture_layer(
+        &mut self,
+        page_size: Vec2<u32>,
+    ) -> Result<(usize, u32)> {
+        let page_idx = self
+            .texture_pages
+            .iter()
+            .position(|page| page.size.to_array() == page_size.to_array());
+        let page_idx = match page_idx {
+            Some(page_idx) => page_idx,
+            None => {
+                let tex = create_texture_array(&self.gl, page_size, 1)?;
+                self.texture_pages.push(TexturePage {
+                    tex,
+                    size: page_size,
+                    n_layers: 0,
+                    capacity: 1,
+                    free_layers: Vec::new(),
+                });
+                self.texture_pages.len() - 1
+            }
+        };
+
+        let page = &mut self.texture_pages[page_idx];
+        if let Some(layer) = page.free_layers.pop() {
+            return Ok((page_idx, layer));
+        }
+
+        // Texture arrays can't be resized, so the full page is copied
+        // to the new one with the doubled capacity
+        if page.n_layers == page.capacity {
+            let capacity = page.capacity * 2;
+            let tex = create_texture_array(&self.gl, page.size, capacity)?;
+            unsafe {
+                self.gl.copy_image_sub_data(
+                    page.tex,
+                    glow::TEXTURE_2D_ARRAY,
+                    0,
+                    0,
+                    0,
+                    0,
+                    tex,
+                    glow::TEXTURE_2D_ARRAY,
+                    0,
+                    0,
+                    0,
+                    0,
+                    page.size.x as i32,
+                    page.size.y as i32,
+                    page.n_layers as i32,
+                );
+                self.gl.delete_texture(page.tex);
+            }
+            page.tex = tex;
+            page.capacity = capacity;
+        }
+
+        page.n_layers += 1;
+
+        Ok((page_idx, page.n_layers - 1))
+    }
+
+    /// The image is placed at the bottom left corner of the layer, so
+    /// the texture pixel coordinates don't depend on the page size. The
+    /// rest of the layer is filled by the extruded image edges, so the
+    /// linear filtering doesn't bleed the stale texels into the sprites.
+    fn upload_texture_layer(
+        &self,
+        page: usize,
+        layer: u32,
+        image: &RgbaImage,
+    ) {
+        let image = extrude_image(image, self.texture_pages[page].size);
+        unsafe {
+            self.gl.bind_texture(
+                glow::TEXTURE_2D_ARRAY,
+                Some(self.texture_pages[page].tex),
+            );
+            self.gl.tex_sub_image_3d(
+                glow::TEXTURE_2D_ARRAY,
+                0,
+                0,
+                0,
+                layer as i32,
+                image.width() as i32,
+                image.height() as i32,
+                1,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelUnpackData::Slice(image.as_raw()),
+            );
         }
     }
 
@@ -256,6 +411,56 @@ impl Renderer {
         self.lights.push(light);
     }
 
+    /// Lays out a single line of text. The pivot is applied to the text
+    /// box, which is `font_size` high and starts at the baseline.
+    pub fn push_text(
+        &mut self,
+        text: &str,
+        pivot: Pivot,
+        space: SpaceType,
+        layer: RenderLayer,
+        font_size: f32,
+        color: Color,
+    ) {
+        let scale = font_size / self.glyph_atlas.font_size;
+        let width: f32 = text
+            .chars()
+            .map(|c| self.glyph_atlas.get_glyph(c).advance * scale)
+            .sum();
+        let rect = Rect::from_pivot(pivot, Vec2::new(width, font_size));
+
+        let mut cursor = rect.get_bot_left();
+        for c in text.chars() {
+            let glyph = self.glyph_atlas.get_glyph(c);
+            let bot_left = cursor + glyph.offset.scale(scale);
+            let size = glyph.xywh.to_size().scale(scale);
+
+            self.primitives.push(DrawPrimitive {
+                z: 0.0,
+                layer,
+                blend: BlendMode::Alpha,
+                rect: Rect::from_bot_left(bot_left, size),
+                space,
+                tex: TextureType::GlyphTexture,
+                texture: Some(self.glyph_atlas_texture),
+                xywh: glyph.xywh,
+                tint: color,
+                flash: Color::only_alpha(0.0),
+                effect: 0,
+                flip_x: false,
+                flip_y: false,
+                transform: Transform::identity(),
+                slice_insets: Insets::default(),
+                slice_scale: 1.0,
+            });
+            cursor.x += glyph.advance * scale;
+        }
+    }
+
+    pub fn get_stats(&self) -> RenderStats {
+        self.stats
+    }
+
     pub fn set_camera(
         &mut self,
         camera_position: Vec2<f32>,
@@ -265,39 +470,210 @@ impl Renderer {
         self.camera_view_size = camera_view_size;
     }
 
+    /// Offscreen RGBA target, which the queue can be rendered into and
+    /// read back from.
+    pub fn create_render_target(
+        &mut self,
+        size: Vec2<u32>,
+    ) -> Result<RenderTargetHandle> {
+        let target = create_render_target(
+            &self.gl,
+            size,
+            glow::RGBA8,
+            glow::UNSIGNED_BYTE,
+        )?;
+
+        let free_idx =
+            self.render_targets.iter().position(|slot| slot.is_none());
+        let idx = match free_idx {
+            Some(idx) => idx,
+            None => {
+                self.render_targets.push(None);
+                self.render_targets.len() - 1
+            }
+        };
+        self.render_targets[idx] = Some(target);
+
+        Ok(RenderTargetHandle(idx))
+    }
+
+    /// The handle is invalid after the deletion.
+    pub fn delete_render_target(&mut self, handle: RenderTargetHandle) {
+        if let Some(target) = self.render_targets[handle.0].take() {
+            delete_render_target(&self.gl, &target);
+        }
+    }
+
+    /// Renders the queue to the window.
     pub fn render(&mut self) {
-        let screen_size =
-            [self.window.size().0 as f32, self.window.size().1 as f32];
+        let (width, height) = self.window.size();
+        self.draw(None, Vec2::new(width, height));
+
+        self.window.gl_swap_window();
+    }
+
+    /// Renders the queue to the target, the screen space is the target
+    /// size. The queue stays, so it can be rendered again.
+    pub fn render_to_target(&mut self, handle: RenderTargetHandle) {
+        let target = self.get_render_target(handle);
+        let (fbo, size) = (target.fbo, target.size);
+
+        self.draw(Some(fbo), size);
+    }
+
+    /// Pixels of the target, the first row is the top one.
+    pub fn read_pixels(&self, handle: RenderTargetHandle) -> RgbaImage {
+        let target = self.get_render_target(handle);
+        let mut pixels =
+            vec![0; (target.size.x * target.size.y * 4) as usize];
+        unsafe {
+            self.gl
+                .bind_framebuffer(glow::FRAMEBUFFER, Some(target.fbo));
+            self.gl.read_pixels(
+                0,
+                0,
+                target.size.x as i32,
+                target.size.y as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(&mut pixels),
+            );
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        }
+
+        let mut image =
+            RgbaImage::from_raw(target.size.x, target.size.y, pixels)
+                .unwrap();
+        flip_vertical_in_place(&mut image);
+
+        image
+    }
+
+    /// Renders the queue to the window-sized target and reads it back.
+    pub fn screenshot(&mut self) -> Result<RgbaImage> {
+        let (width, height) = self.window.size();
+        let target =
+            self.create_render_target(Vec2::new(width, height))?;
+        self.render_to_target(target);
+        let image = self.read_pixels(target);
+        self.delete_render_target(target);
+
+        Ok(image)
+    }
+
+    fn get_render_target(
+        &self,
+        handle: RenderTargetHandle,
+    ) -> &RenderTarget {
+        self.render_targets[handle.0]
+            .as_ref()
+            .expect("Render target should exist")
+    }
+
+    fn draw(
+        &mut self,
+        fbo: Option<glow::NativeFramebuffer>,
+        size: Vec2<u32>,
+    ) {
+        let screen_size = [size.x as f32, size.y as f32];
         let camera_xywh = [
             self.camera_position.to_array(),
             self.camera_view_size.to_array(),
         ]
         .concat();
-        // Sort draw primitives by their z-value
-        self.primitives
-            .sort_by(|a, b| a.z.partial_cmp(&b.z).unwrap());
+        // Sort draw primitives by their layer and z-value. The sort is
+        // stable, so the primitives with the same z are drawn in the
+        // push order
+        self.primitives.sort_by(|a, b| {
+            a.layer.cmp(&b.layer).then(a.z.partial_cmp(&b.z).unwrap())
+        });
+
+        // Split primitives into batches. A new batch starts when the
+        // current one is full, it can't bind one more texture page or
+        // the blend mode changes
+        self.batches.clear();
+        for (idx, primitive) in self.primitives.iter().enumerate() {
+            let page = primitive
+                .texture
+                .map(|texture| self.textures[texture.0].page);
+            let is_new_batch = match self.batches.last() {
+                None => true,
+                Some(batch) => {
+                    batch.count == MAX_N_INSTANCED_PRIMITIVES
+                        || batch.blend != primitive.blend
+                        || page.is_some_and(|page| {
+                            !batch.pages.contains(&page)
+                                && batch.pages.len()
+                                    == MAX_N_BOUND_TEXTURE_PAGES
+                        })
+                }
+            };
+            if is_new_batch {
+                self.batches.push(Batch {
+                    start: idx,
+                    count: 0,
+                    pages: Vec::new(),
+                    blend: primitive.blend,
+                });
+            }
+
+            let batch = self.batches.last_mut().unwrap();
+            batch.count += 1;
+
+            // The shader gets the texture unit of the page, not the page
+            let tex_page_layer = match primitive.texture {
+                Some(texture) => {
+                    let slot = self.textures[texture.0];
+                    let unit = match batch
+                        .pages
+                        .iter()
+                        .position(|&page| page == slot.page)
+                    {
+                        Some(unit) => unit,
+                        None => {
+                            batch.pages.push(slot.page);
+                            batch.pages.len() - 1
+                        }
+                    };
+                    [unit as u32, slot.layer]
+                }
+                None => [0, 0],
+            };
 
-        // Fill up draw primitives related shader vao attributes
-        for primitive in self.primitives.iter() {
-            self.a_xywh.push_data(&primitive.rect.to_xywh());
-            self.a_space.push_data(&[primitive.space as u32]);
-            self.a_effect.push_data(&[primitive.effect]);
-            self.a_flip.push_data(&[(primitive.flip as i32) as f32]);
-            self.a_tex_id.push_data(&[primitive.tex as u32]);
-            self.a_tex_uvwh.push_data(&primitive.xywh.to_array());
-            self.a_rgba.push_data(&primitive.color.to_rbga());
+            self.primitive_instances.push(PrimitiveInstance {
+                xywh: primitive.rect.to_xywh(),
+                tex_uvwh: primitive.xywh.to_array(),
+                tint: primitive.tint.to_rbga(),
+                flash: primitive.flash.to_rbga(),
+                space: primitive.space as u32,
+                effect: primitive.effect,
+                tex_id: primitive.tex as u32,
+                flip: [
+                    (primitive.flip_x as i32) as f32,
+                    (primitive.flip_y as i32) as f32,
+                ],
+                tex_page_layer,
+                transform: primitive.transform.to_mat2(),
+                translation: primitive.transform.translation.to_array(),
+                slice_insets: [
+                    primitive.slice_insets.left as f32,
+                    primitive.slice_insets.bottom as f32,
+                    primitive.slice_insets.right as f32,
+                    primitive.slice_insets.top as f32,
+                ],
+                slice_scale: primitive.slice_scale,
+            });
         }
 
+        self.stats = RenderStats {
+            n_primitives: self.primitives.len(),
+            n_batches: self.batches.len(),
+        };
+
         // Render primitives
         unsafe {
             self.gl.bind_vertex_array(Some(self.primitive_vao));
-            self.a_xywh.sync_data(&self.gl);
-            self.a_space.sync_data(&self.gl);
-            self.a_effect.sync_data(&self.gl);
-            self.a_rgba.sync_data(&self.gl);
-            self.a_tex_uvwh.sync_data(&self.gl);
-            self.a_tex_id.sync_data(&self.gl);
-            self.a_flip.sync_data(&self.gl);
+            self.primitive_instances.sync_data(&self.gl);
 
             self.gl.use_program(Some(self.primitive_program));
             set_uniform_2_f32(
@@ -313,23 +689,14 @@ impl Renderer {
                 &camera_xywh,
             );
 
-            set_uniform_1_i32(
-                &self.gl,
-                self.primitive_program,
-                "sprite_atlas_tex",
-                0,
-            );
-            self.gl.active_texture(glow::TEXTURE0 + 0);
-            self.gl.bind_texture(
-                glow::TEXTURE_2D,
-                Some(self.sprite_atlas_tex),
-            );
-
-            // if let Some(&self.glyph_atlas_tex) = self.&self.glyph_atlas_tex {
-            //     set_uniform_1_i32(&self.gl, self.program, "&self.glyph_atlas_tex", 1);
-            //     &self.gl.active_texture(glow::TEXTURE0 + 1);
-            //     &self.gl.bind_texture(glow::TEXTURE_2D, Some(&self.glyph_atlas_tex));
-            // }
+            for i in 0..MAX_N_BOUND_TEXTURE_PAGES {
+                set_uniform_1_i32(
+                    &self.gl,
+                    self.primitive_program,
+                    &format!("texture_pages[{}]", i),
+                    i as i32,
+                );
+            }
 
             set_uniform_1_i32(
                 &self.gl,
@@ -359,28 +726,75 @@ impl Renderer {
                 );
             }
 
+            if self.hdr_buffer.size.to_array() != size.to_array() {
+                self.gl.bind_texture(
+                    glow::TEXTURE_2D,
+                    Some(self.hdr_buffer.tex),
+                );
+                self.gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    glow::RGBA32F as i32,
+                    size.x as i32,
+                    size.y as i32,
+                    0,
+                    glow::RGBA,
+                    glow::FLOAT,
+                    None,
+                );
+                self.hdr_buffer.size = size;
+            }
+
             self.gl.enable(glow::BLEND);
-            self.gl
-                .blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
-            self.gl.viewport(
-                0,
-                0,
-                self.hdr_buffer_size.x as i32,
-                self.hdr_buffer_size.y as i32,
+            self.gl.viewport(0, 0, size.x as i32, size.y as i32);
+            self.gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                Some(self.hdr_buffer.fbo),
             );
-            self.gl
-                .bind_framebuffer(glow::FRAMEBUFFER, Some(self.hdr_fbo));
             self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
             self.gl
                 .clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
-            self.gl.draw_arrays_instanced(
-                glow::TRIANGLE_STRIP,
-                0,
-                4,
-                self.primitives.len() as i32,
-            );
+            for batch in self.batches.iter() {
+                for (unit, &page) in batch.pages.iter().enumerate() {
+                    self.gl.active_texture(glow::TEXTURE0 + unit as u32);
+                    self.gl.bind_texture(
+                        glow::TEXTURE_2D_ARRAY,
+                        Some(self.texture_pages[page].tex),
+                    );
+                }
+
+                // The shader outputs premultiplied colors
+                let (src_factor, dst_factor) = match batch.blend {
+                    BlendMode::Alpha => {
+                        (glow::ONE, glow::ONE_MINUS_SRC_ALPHA)
+                    }
+                    BlendMode::Additive => (glow::ONE, glow::ONE),
+                    BlendMode::Multiply => {
+                        (glow::DST_COLOR, glow::ONE_MINUS_SRC_ALPHA)
+                    }
+                    BlendMode::Premultiplied => {
+                        (glow::ONE, glow::ONE_MINUS_SRC_ALPHA)
+                    }
+                };
+                self.gl.blend_func(src_factor, dst_factor);
+                set_uniform_1_u32(
+                    &self.gl,
+                    self.primitive_program,
+                    "blend_mode",
+                    batch.blend as u32,
+                );
+
+                self.gl.draw_arrays_instanced_base_instance(
+                    glow::TRIANGLE_STRIP,
+                    0,
+                    4,
+                    batch.count as i32,
+                    batch.start as u32,
+                );
+            }
 
             // Resolve hdr buffer
+            self.gl.disable(glow::BLEND);
             self.gl.use_program(Some(self.hdr_resolve_program));
             set_uniform_1_i32(
                 &self.gl,
@@ -390,19 +804,13 @@ impl Renderer {
             );
 
             self.gl.active_texture(glow::TEXTURE0 + 0);
-            self.gl.bind_texture(glow::TEXTURE_2D, Some(self.hdr_tex));
+            self.gl
+                .bind_texture(glow::TEXTURE_2D, Some(self.hdr_buffer.tex));
 
-            self.gl.viewport(
-                0,
-                0,
-                screen_size[0] as i32,
-                screen_size[1] as i32,
-            );
-            self.gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, fbo);
             self.gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, None);
         }
-
-        self.window.gl_swap_window();
     }
 
     /*
@@ -471,82 +879,249 @@ impl Renderer {
     */
 }
 
-pub struct Attribute<T> {
-    pub data: Vec<T>,
-    pub vbo: glow::NativeBuffer,
+/// Per-primitive data of the instanced draw. The vertex shader inputs
+/// are generated from `PRIMITIVE_INSTANCE_ATTRIBUTES`, so the shaders
+/// don't declare them.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct PrimitiveInstance {
+    xywh: [f32; 4],
+    tex_uvwh: [f32; 4],
+    tint: [f32; 4],
+    flash: [f32; 4],
+    space: u32,
+    effect: u32,
+    tex_id: u32,
+    flip: [f32; 2],
+    tex_page_layer: [u32; 2],
+    transform: [f32; 4],
+    translation: [f32; 2],
+    slice_insets: [f32; 4],
+    slice_scale: f32,
 }
 
-impl<T> Attribute<T>
-where
-    for<'a> &'a [T]: IntoIterator<Item = &'a T>,
-    T: Clone,
-{
-    pub fn new(
+const PRIMITIVE_INSTANCE_ATTRIBUTES: [InstanceAttribute; 13] = [
+    InstanceAttribute {
+        name: "a_xywh",
+        size: 4,
+        data_type: glow::FLOAT,
+        offset: offset_of!(PrimitiveInstance, xywh),
+    },
+    InstanceAttribute {
+        name: "a_space",
+        size: 1,
+        data_type: glow::UNSIGNED_INT,
+        offset: offset_of!(PrimitiveInstance, space),
+    },
+    InstanceAttribute {
+        name: "a_effect",
+        size: 1,
+        data_type: glow::UNSIGNED_INT,
+        offset: offset_of!(PrimitiveInstance, effect),
+    },
+    InstanceAttribute {
+        name: "a_tex_uvwh",
+        size: 4,
+        data_type: glow::FLOAT,
+        offset: offset_of!(PrimitiveInstance, tex_uvwh),
+    },
+    InstanceAttribute {
+        name: "a_tint",
+        size: 4,
+        data_type: glow::FLOAT,
+        offset: offset_of!(PrimitiveInstance, tint),
+    },
+    InstanceAttribute {
+        name: "a_flash",
+        size: 4,
+        data_type: glow::FLOAT,
+        offset: offset_of!(PrimitiveInstance, flash),
+    },
+    InstanceAttribute {
+        name: "a_tex_id",
+        size: 1,
+        data_type: glow::UNSIGNED_INT,
+        offset: offset_of!(PrimitiveInstance, tex_id),
+    },
+    InstanceAttribute {
+        name: "a_flip",
+        size: 2,
+        data_type: glow::FLOAT,
+        offset: offset_of!(PrimitiveInstance, flip),
+    },
+    InstanceAttribute {
+        name: "a_tex_page_layer",
+        size: 2,
+        data_type: glow::UNSIGNED_INT,
+        offset: offset_of!(PrimitiveInstance, tex_page_layer),
+    },
+    InstanceAttribute {
+        name: "a_transform",
+        size: 4,
+        data_type: glow::FLOAT,
+        offset: offset_of!(PrimitiveInstance, transform),
+    },
+    InstanceAttribute {
+        name: "a_translation",
+        size: 2,
+        data_type: glow::FLOAT,
+        offset: offset_of!(PrimitiveInstance, translation),
+    },
+    InstanceAttribute {
+        name: "a_slice_insets",
+        size: 4,
+        data_type: glow::FLOAT,
+        offset: offset_of!(PrimitiveInstance, slice_insets),
+    },
+    InstanceAttribute {
+        name: "a_slice_scale",
+        size: 1,
+        data_type: glow::FLOAT,
+        offset: offset_of!(PrimitiveInstance, slice_scale),
+    },
+];
+
+/// Field of the instance struct. Its location is the index in the
+/// attributes slice.
+struct InstanceAttribute {
+    name: &'static str,
+    size: usize,
+    data_type: u32,
+    offset: usize,
+}
+
+impl InstanceAttribute {
+    const fn get_glsl_type(&self) -> &'static str {
+        match (self.data_type, self.size) {
+            (glow::FLOAT, 1) => "float",
+            (glow::FLOAT, 2) => "vec2",
+            (glow::FLOAT, 3) => "vec3",
+            (glow::FLOAT, 4) => "vec4",
+            (glow::UNSIGNED_INT, 1) => "uint",
+            (glow::UNSIGNED_INT, 2) => "uvec2",
+            (glow::UNSIGNED_INT, 3) => "uvec3",
+            (glow::UNSIGNED_INT, 4) => "uvec4",
+            (glow::INT, 1) => "int",
+            (glow::INT, 2) => "ivec2",
+            (glow::INT, 3) => "ivec3",
+            (glow::INT, 4) => "ivec4",
+            _ => panic!("Unsupported instance attribute type"),
+        }
+    }
+}
+
+// Unsupported attribute types fail the build instead of the shader
+// generation at runtime
+const _: () = {
+    let mut i = 0;
+    while i < PRIMITIVE_INSTANCE_ATTRIBUTES.len() {
+        PRIMITIVE_INSTANCE_ATTRIBUTES[i].get_glsl_type();
+        i += 1;
+    }
+};
+
+fn instance_attributes_to_shader_source(
+    attributes: &[InstanceAttribute],
+) -> String {
+    let mut source = String::new();
+
+    for (loc, attribute) in attributes.iter().enumerate() {
+        let definition = format!(
+            "layout (location = {}) in {} {};\n",
+            loc,
+            attribute.get_glsl_type(),
+            attribute.name
+        );
+        source.push_str(&definition);
+    }
+
+    source
+}
+
+/// Interleaved instance data, uploaded by a single call per frame. The
+/// buffer grows if more instances are pushed than it can hold.
+pub struct InstanceBuffer<T> {
+    data: Vec<T>,
+    vbo: glow::NativeBuffer,
+    vbo_size: usize,
+}
+
+impl<T: Copy> InstanceBuffer<T> {
+    /// Binds the attributes to the currently bound vertex array.
+    fn new(
         gl: &glow::Context,
-        program: glow::NativeProgram,
-        size: usize,
-        name: &'static str,
-        data_type: u32,
+        attributes: &[InstanceAttribute],
         max_n_instances: usize,
-        divisor: u32,
-    ) -> Self {
-        let max_n_elements = max_n_instances * size;
-        let vbo_size = size_of::<T>() * max_n_elements;
-        let data = Vec::<T>::with_capacity(max_n_elements);
-        let vbo = create_vbo(gl, vbo_size, glow::DYNAMIC_DRAW);
+    ) -> Result<Self> {
+        let vbo_size = size_of::<T>() * max_n_instances;
+        let vbo = create_vbo(gl, vbo_size, glow::DYNAMIC_DRAW)?;
+        let stride = size_of::<T>() as i32;
 
         unsafe {
-            let loc = match gl.get_attrib_location(program, name) {
-                Some(loc) => loc,
-                None => {
-                    panic!("Can't obtain attribute location: {}", name)
+            for (loc, attribute) in attributes.iter().enumerate() {
+                let loc = loc as u32;
+                let size = attribute.size as i32;
+                let offset = attribute.offset as i32;
+                gl.enable_vertex_attrib_array(loc);
+
+                match attribute.data_type {
+                    glow::FLOAT => {
+                        gl.vertex_attrib_pointer_f32(
+                            loc,
+                            size,
+                            attribute.data_type,
+                            false,
+                            stride,
+                            offset,
+                        );
+                    }
+                    glow::INT | glow::UNSIGNED_INT => {
+                        gl.vertex_attrib_pointer_i32(
+                            loc,
+                            size,
+                            attribute.data_type,
+                            stride,
+                            offset,
+                        );
+                    }
+                    _ => {
+                        return Err(Error::Gl(format!(
+                            "Unsupported vertex attrib data type: {}",
+                            attribute.data_type
+                        )));
+                    }
                 }
-            };
 
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
-            gl.enable_vertex_attrib_array(loc);
-
-            match data_type {
-                glow::FLOAT => {
-                    gl.vertex_attrib_pointer_f32(
-                        loc,
-                        size as i32,
-                        data_type,
-                        false,
-                        0,
-                        0,
-                    );
-                }
-                glow::INT | glow::UNSIGNED_INT => {
-                    gl.vertex_attrib_pointer_i32(
-                        loc,
-                        size as i32,
-                        data_type,
-                        0,
-                        0,
-                    );
-                }
-                _ => {
-                    panic!(
-                        "Unsopported vertex attrib data type: {}",
-                        data_type
-                    );
-                }
+                gl.vertex_attrib_divisor(loc, 1);
             }
-
-            gl.vertex_attrib_divisor(loc, divisor);
         }
 
-        Self { data, vbo }
+        Ok(Self {
+            data: Vec::with_capacity(max_n_instances),
+            vbo,
+            vbo_size,
+        })
     }
 
-    pub fn push_data(&mut self, data: &[T]) {
-        self.data.extend_from_slice(data);
+    fn push(&mut self, instance: T) {
+        self.data.push(instance);
     }
 
-    pub fn sync_data(&mut self, gl: &glow::Context) {
+    fn sync_data(&mut self, gl: &glow::Context) {
+        let data_size = size_of::<T>() * self.data.len();
         unsafe {
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
+
+            // The storage is re-allocated (orphaned) each frame, so the
+            // upload doesn't wait for the previous frame draw calls.
+            // Re-allocating keeps the vertex array pointing to the buffer
+            self.vbo_size =
+                self.vbo_size.max(data_size.next_power_of_two());
+            gl.buffer_data_size(
+                glow::ARRAY_BUFFER,
+                self.vbo_size as i32,
+                glow::DYNAMIC_DRAW,
+            );
             gl.buffer_sub_data_u8_slice(
                 glow::ARRAY_BUFFER,
                 0,
@@ -561,92 +1136,316 @@ fn create_vbo(
     gl: &glow::Context,
     size: usize,
     usage: u32,
-) -> glow::NativeBuffer {
+) -> Result<glow::NativeBuffer> {
     let vbo;
 
     unsafe {
-        vbo = gl.create_buffer().unwrap();
+        vbo = gl.create_buffer().map_err(Error::Gl)?;
         gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
         gl.buffer_data_size(glow::ARRAY_BUFFER, size as i32, usage);
     }
 
-    vbo
+    Ok(vbo)
 }
 
-fn create_vao(gl: &glow::Context) -> glow::NativeVertexArray {
+fn create_vao(gl: &glow::Context) -> Result<glow::NativeVertexArray> {
     let vao;
 
     unsafe {
-        vao = gl.create_vertex_array().unwrap();
+        vao = gl.create_vertex_array().map_err(Error::Gl)?;
     }
 
-    vao
+    Ok(vao)
+}
+
+/// Sources, which the shaders include by their names instead of the
+/// files. They are generated from the Rust side, so the shaders can't
+/// get out of sync with it.
+fn generated_shader_sources() -> [(&'static str, String); 2] {
+    let mut constants = String::new();
+    constants.push_str(&enum_to_shader_source::<SpaceType>());
+    constants.push_str(&enum_to_shader_source::<TextureType>());
+    constants.push_str(&enum_to_shader_source::<EffectType>());
+    constants.push_str(&enum_to_shader_source::<BlendMode>());
+    constants.push_str(&format!(
+        "const int MAX_N_BOUND_TEXTURE_PAGES = {};\n",
+        MAX_N_BOUND_TEXTURE_PAGES
+    ));
+    constants.push_str(&format!(
+        "const int MAX_N_LIGHTS = {};\n",
+        MAX_N_LIGHTS
+    ));
+
+    [
+        ("constants.glsl", constants),
+        (
+            "instance_attributes.glsl",
+            instance_attributes_to_shader_source(
+                &PRIMITIVE_INSTANCE_ATTRIBUTES,
+            ),
+        ),
+    ]
 }
 
 fn create_program(
     gl: &glow::Context,
-    common_shader_fp: Option<&str>,
     vert_shader_fp: &str,
     frag_shader_fp: &str,
-) -> glow::NativeProgram {
+) -> Result<glow::NativeProgram> {
+    let generated = generated_shader_sources();
+    let generated: Vec<_> = generated
+        .iter()
+        .map(|(name, source)| (*name, source.as_str()))
+        .collect();
+    let vert_shader =
+        ShaderSource::preprocess(vert_shader_fp, &generated)?;
+    let frag_shader =
+        ShaderSource::preprocess(frag_shader_fp, &generated)?;
+
     let program;
 
     unsafe {
-        program = gl.create_program().expect("Cannot create program");
-
-        let mut common_shader_src: String;
-        if let Some(common_shader_fp) = common_shader_fp {
-            common_shader_src =
-                fs::read_to_string(common_shader_fp).unwrap();
-        } else {
-            common_shader_src = "".to_string();
-        }
-
-        common_shader_src.push_str(&enum_to_shader_source::<SpaceType>());
-        common_shader_src
-            .push_str(&enum_to_shader_source::<TextureType>());
-        common_shader_src.push_str(&enum_to_shader_source::<EffectType>());
-
-        let mut vert_shader_src =
-            fs::read_to_string(vert_shader_fp).unwrap();
-        vert_shader_src = common_shader_src.clone() + &vert_shader_src;
-
-        let mut frag_shader_src =
-            fs::read_to_string(frag_shader_fp).unwrap();
-        frag_shader_src = common_shader_src.clone() + &frag_shader_src;
+        program = gl.create_program().map_err(Error::Gl)?;
 
         let shaders_src = [
-            (glow::VERTEX_SHADER, vert_shader_src),
-            (glow::FRAGMENT_SHADER, frag_shader_src),
+            (glow::VERTEX_SHADER, vert_shader_fp, vert_shader),
+            (glow::FRAGMENT_SHADER, frag_shader_fp, frag_shader),
         ];
 
         let mut shaders = Vec::with_capacity(shaders_src.len());
 
-        for (shader_type, shader_src) in shaders_src.iter() {
-            let shader = gl
-                .create_shader(*shader_type)
-                .expect("Cannot create shader");
-            gl.shader_source(shader, shader_src);
-            gl.compile_shader(shader);
-            if !gl.get_shader_compile_status(shader) {
-                panic!("{}", gl.get_shader_info_log(shader));
-            }
+        for (shader_type, shader_fp, shader_src) in shaders_src.iter() {
+            let shader = match compile_shader(
+                gl,
+                *shader_type,
+                shader_fp,
+                shader_src,
+            ) {
+                Ok(shader) => shader,
+                Err(e) => {
+                    for shader in shaders {
+                        gl.delete_shader(shader);
+                    }
+                    gl.delete_program(program);
+
+                    return Err(e);
+                }
+            };
             gl.attach_shader(program, shader);
             shaders.push(shader);
         }
 
         gl.link_program(program);
-        if !gl.get_program_link_status(program) {
-            panic!("{}", gl.get_program_info_log(program));
-        }
+        let is_linked = gl.get_program_link_status(program);
 
         for shader in shaders {
             gl.detach_shader(program, shader);
             gl.delete_shader(shader);
         }
+
+        if !is_linked {
+            let log = gl.get_program_info_log(program);
+            gl.delete_program(program);
+
+            return Err(Error::Link {
+                fps: vec![
+                    vert_shader_fp.to_string(),
+                    frag_shader_fp.to_string(),
+                ],
+                log,
+            });
+        }
+    }
+
+    Ok(program)
+}
+
+fn compile_shader(
+    gl: &glow::Context,
+    shader_type: u32,
+    shader_fp: &str,
+    shader_src: &ShaderSource,
+) -> Result<glow::NativeShader> {
+    unsafe {
+        let shader = gl.create_shader(shader_type).map_err(Error::Gl)?;
+        gl.shader_source(shader, &shader_src.source);
+        gl.compile_shader(shader);
+        if !gl.get_shader_compile_status(shader) {
+            let log = shader_src.map_log(&gl.get_shader_info_log(shader));
+            gl.delete_shader(shader);
+
+            return Err(Error::Shader {
+                fp: shader_fp.to_string(),
+                log,
+            });
+        }
+
+        Ok(shader)
+    }
+}
+
+/// Compiles the `.glsl` libraries on their own, each is included by
+/// the stub fragment shader. The programs compile only the libraries,
+/// which they include, so the unused ones are checked this way.
+fn check_shader_libraries(gl: &glow::Context) -> Result<()> {
+    let generated = generated_shader_sources();
+    for fp in SHADER_FPS.iter().filter(|fp| fp.ends_with(".glsl")) {
+        let name = Path::new(fp).file_name().unwrap().to_string_lossy();
+        let stub_fp = format!("{}.frag", fp);
+        let stub = format!(
+            "#version 450 core\n#include \"{}\"\nvoid main() {{}}\n",
+            name
+        );
+
+        let mut sources: Vec<_> = generated
+            .iter()
+            .map(|(name, source)| (*name, source.as_str()))
+            .collect();
+        sources.push((&stub_fp, &stub));
+        let shader_src = ShaderSource::preprocess(&stub_fp, &sources)?;
+
+        let shader =
+            compile_shader(gl, glow::FRAGMENT_SHADER, fp, &shader_src)?;
+        unsafe { gl.delete_shader(shader) };
+    }
+
+    Ok(())
+}
+
+/// The window uses the attributes, so they should be set before it's
+/// created.
+fn set_gl_attributes(video: &sdl2::VideoSubsystem) {
+    let gl_attr = video.gl_attr();
+    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+    gl_attr.set_context_version(4, 5);
+}
+
+fn create_render_target(
+    gl: &glow::Context,
+    size: Vec2<u32>,
+    internal_format: u32,
+    ty: u32,
+) -> Result<RenderTarget> {
+    let tex = create_texture(
+        gl,
+        internal_format as i32,
+        size.x as i32,
+        size.y as i32,
+        glow::RGBA,
+        ty,
+        None,
+        glow::NEAREST,
+    )?;
+
+    let fbo;
+    unsafe {
+        fbo = gl.create_framebuffer().map_err(Error::Gl)?;
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+        gl.framebuffer_texture_2d(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::TEXTURE_2D,
+            Some(tex),
+            0,
+        );
+        gl.draw_buffer(glow::COLOR_ATTACHMENT0);
+        let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+        if status != glow::FRAMEBUFFER_COMPLETE {
+            gl.delete_framebuffer(fbo);
+            gl.delete_texture(tex);
+            return Err(Error::Gl(format!(
+                "Framebuffer is incomplete: {:#x}",
+                status
+            )));
+        }
+    }
+
+    Ok(RenderTarget { fbo, tex, size })
+}
+
+fn delete_render_target(gl: &glow::Context, target: &RenderTarget) {
+    unsafe {
+        gl.delete_framebuffer(target.fbo);
+        gl.delete_texture(target.tex);
+    }
+}
+
+fn read_texture_image(image_fp: &str) -> Result<RgbaImage> {
+    let mut image = ImageReader::open(image_fp)
+        .map_err(Error::io(image_fp))?
+        .decode()
+        .map_err(|source| Error::Image {
+            fp: image_fp.to_string(),
+            source,
+        })?;
+    flip_vertical_in_place(&mut image);
+
+    Ok(image.to_rgba8())
+}
+
+fn get_page_size(image: &RgbaImage) -> Vec2<u32> {
+    Vec2::new(
+        image.width().next_power_of_two(),
+        image.height().next_power_of_two(),
+    )
+}
+
+/// Texture layers are extruded from the image edges, so the image
+/// can't be empty.
+fn check_texture_image(image: &RgbaImage) -> Result<()> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err(Error::EmptyTexture);
+    }
+
+    Ok(())
+}
+
+/// Extends the image to the size by repeating its right and top
+/// (last row) edge pixels.
+fn extrude_image(image: &RgbaImage, size: Vec2<u32>) -> RgbaImage {
+    let (w, h) = image.dimensions();
+
+    RgbaImage::from_fn(size.x, size.y, |x, y| {
+        *image.get_pixel(x.min(w - 1), y.min(h - 1))
+    })
+}
+
+fn create_texture_array(
+    gl: &glow::Context,
+    size: Vec2<u32>,
+    n_layers: u32,
+) -> Result<glow::Texture> {
+    let tex;
+
+    unsafe {
+        tex = gl.create_texture().map_err(Error::Gl)?;
+        gl.bind_texture(glow::TEXTURE_2D_ARRAY, Some(tex));
+        gl.tex_storage_3d(
+            glow::TEXTURE_2D_ARRAY,
+            1,
+            glow::RGBA8,
+            size.x as i32,
+            size.y as i32,
+            n_layers as i32,
+        );
+
+        for (param, value) in [
+            (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+            (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+        ] {
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D_ARRAY,
+                param,
+                value as i32,
+            );
+        }
     }
 
-    program
+    Ok(tex)
 }
 
 fn create_texture(
@@ -658,11 +1457,11 @@ fn create_texture(
     ty: u32,
     pixels: Option<&[u8]>,
     filter: u32,
-) -> glow::Texture {
+) -> Result<glow::Texture> {
     let tex;
 
     unsafe {
-        tex = gl.create_texture().unwrap();
+        tex = gl.create_texture().map_err(Error::Gl)?;
         gl.bind_texture(glow::TEXTURE_2D, Some(tex));
         gl.tex_image_2d(
             glow::TEXTURE_2D,
@@ -698,7 +1497,7 @@ fn create_texture(
         );
     }
 
-    tex
+    Ok(tex)
 }
 
 fn set_uniform_1_f32(
@@ -725,6 +1524,18 @@ fn set_uniform_1_i32(
     }
 }
 
+fn set_uniform_1_u32(
+    gl: &glow::Context,
+    program: glow::NativeProgram,
+    name: &str,
+    value: u32,
+) {
+    unsafe {
+        let loc = gl.get_uniform_location(program, name);
+        gl.uniform_1_u32(loc.as_ref(), value)
+    }
+}
+
 fn set_uniform_2_f32(
     gl: &glow::Context,
     program: glow::NativeProgram,
@@ -773,25 +1584,75 @@ fn cast_slice_to_u8<T>(slice: &[T]) -> &[u8] {
     casted
 }
 
+/// Texture loaded to the renderer, it stays valid after the reload.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TextureHandle(usize);
+
+/// Offscreen target created by the renderer.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct RenderTargetHandle(usize);
+
+/// Framebuffer with a single color texture.
+struct RenderTarget {
+    fbo: glow::NativeFramebuffer,
+    tex: glow::Texture,
+    size: Vec2<u32>,
+}
+
+#[derive(Copy, Clone)]
+struct TextureSlot {
+    page: usize,
+    layer: u32,
+}
+
+struct TexturePage {
+    tex: glow::Texture,
+    size: Vec2<u32>,
+    /// Number of the used layers, including the freed ones
+    n_layers: u32,
+    capacity: u32,
+    /// Layers of the textures, which moved to another page
+    free_layers: Vec<u32>,
+}
+
+/// The `rect` is placed in its space by the `transform`. The `tint`
+/// multiplies the texture color and the `flash` is added on top of
+/// the lit color, scaled by its alpha. The slice insets are used only
+/// by the `NineSliceTexture`.
 pub struct DrawPrimitive {
     pub z: f32,
+    pub layer: RenderLayer,
+    pub blend: BlendMode,
     pub rect: Rect,
     pub space: SpaceType,
     pub tex: TextureType,
+    pub texture: Option<TextureHandle>,
     pub xywh: XYWH,
-    pub color: Color,
+    pub tint: Color,
+    pub flash: Color,
     pub effect: u32,
-    pub flip: bool,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub transform: Transform,
+    pub slice_insets: Insets,
+    pub slice_scale: f32,
 }
 
 impl DrawPrimitive {
     pub fn world_sprite(
+        texture: TextureHandle,
         xywh: XYWH,
         pivot: Pivot,
         apply_light: bool,
-        flip: bool,
+        frame_transform: FrameTransform,
     ) -> Self {
+        // Sprites are rotated around their center, like the frame masks
         let rect = Rect::from_pivot(pivot, xywh.to_size());
+        let transform = Transform::from_pivot(
+            rect.get_center(),
+            frame_transform.rotation,
+            Vec2::new(1.0, 1.0),
+        );
         let effect = if apply_light {
             EffectType::ApplyLightEffect as u32
         } else {
@@ -800,26 +1661,73 @@ impl DrawPrimitive {
 
         Self {
             z: 0.0,
+            layer: RenderLayer::Entities,
+            blend: BlendMode::Alpha,
             rect,
             space: SpaceType::WorldSpace,
             tex: TextureType::SpriteTexture,
+            texture: Some(texture),
             xywh,
-            color: Color::only_alpha(1.0),
+            tint: Color::gray(1.0, 1.0),
+            flash: Color::only_alpha(0.0),
+            effect,
+            flip_x: frame_transform.flip_x,
+            flip_y: frame_transform.flip_y,
+            transform,
+            slice_insets: Insets::default(),
+            slice_scale: 1.0,
+        }
+    }
+
+    /// Sprite stretched over the rect with its borders kept. The
+    /// `scale` is the size of the sprite pixel in the space units, so
+    /// the borders don't depend on the rect size.
+    pub fn nine_slice(
+        texture: TextureHandle,
+        xywh: XYWH,
+        insets: Insets,
+        rect: Rect,
+        space: SpaceType,
+        scale: f32,
+    ) -> Self {
+        Self {
+            z: 0.0,
+            layer: RenderLayer::UI,
+            blend: BlendMode::Alpha,
+            rect,
+            space,
+            tex: TextureType::NineSliceTexture,
+            texture: Some(texture),
+            xywh,
+            tint: Color::gray(1.0, 1.0),
+            flash: Color::only_alpha(0.0),
             effect: 0,
-            flip,
+            flip_x: false,
+            flip_y: false,
+            transform: Transform::identity(),
+            slice_insets: insets,
+            slice_scale: scale,
         }
     }
 
     pub fn world_rect(rect: Rect, color: Color) -> Self {
         Self {
             z: 0.0,
+            layer: RenderLayer::Entities,
+            blend: BlendMode::Alpha,
             rect,
             space: SpaceType::WorldSpace,
             tex: TextureType::ProceduralTexture,
+            texture: None,
             xywh: XYWH::zeros(),
-            color,
+            tint: color,
+            flash: Color::only_alpha(0.0),
             effect: 0,
-            flip: false,
+            flip_x: false,
+            flip_y: false,
+            transform: Transform::identity(),
+            slice_insets: Insets::default(),
+            slice_scale: 1.0,
         }
     }
 }
@@ -830,6 +1738,22 @@ pub struct DrawLight {
     attenuation: [f32; 3],
 }
 
+impl DrawLight {
+    /// The attenuation is (constant, linear, quadratic) factors of the
+    /// distance to the light.
+    pub fn new(
+        position: Vec2<f32>,
+        color: Color,
+        attenuation: [f32; 3],
+    ) -> Self {
+        Self {
+            position,
+            color,
+            attenuation,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Sequence)]
 pub enum SpaceType {
     WorldSpace = 1,
@@ -847,6 +1771,7 @@ pub enum TextureType {
     ProceduralTexture = 1,
     SpriteTexture = 2,
     GlyphTexture = 3,
+    NineSliceTexture = 4,
 }
 impl From<TextureType> for u32 {
     fn from(e: TextureType) -> u32 {
@@ -865,6 +1790,33 @@ impl From<EffectType> for u32 {
     }
 }
 
+/// Layers are drawn in the declaration order, the primitives inside a
+/// layer are sorted by their z.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+pub enum RenderLayer {
+    Background,
+    Tiles,
+    Entities,
+    Effects,
+    UI,
+    Debug,
+}
+
+/// `Premultiplied` is the alpha blending of the colors, which are
+/// already multiplied by their alpha.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Sequence)]
+pub enum BlendMode {
+    Alpha = 1,
+    Additive = 2,
+    Multiply = 3,
+    Premultiplied = 4,
+}
+impl From<BlendMode> for u32 {
+    fn from(e: BlendMode) -> u32 {
+        e as u32
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Color {
     pub r: f32,
@@ -917,6 +1869,10 @@ impl Color {
         Self::new(1.0, 1.0, 0.0, a)
     }
 
+    pub fn blue(a: f32) -> Self {
+        Self::new(0.0, 0.0, 1.0, a)
+    }
+
     pub fn to_rbga(&self) -> [f32; 4] {
         [self.r, self.g, self.b, self.a]
     }
@@ -932,9 +1888,13 @@ impl Color {
     }
 }
 
+/// Constant per variant, named as the variant. The source starts with
+/// the enum name comment, so the generated code is easier to read.
 pub fn enum_to_shader_source<T: Sequence + Debug + Copy + Into<u32>>(
 ) -> String {
-    let mut source = String::new();
+    let type_name = std::any::type_name::<T>();
+    let enum_name = type_name.rsplit("::").next().unwrap_or(type_name);
+    let mut source = format!("// {}\n", enum_name);
 
     for variant in all::<T>().collect::<Vec<_>>() {
         let definition =
@@ -944,3 +1904,268 @@ pub fn enum_to_shader_source<T: Sequence + Debug + Copy + Into<u32>>(
 
     source
 }
+
+/// Compiles all the programs and the shader libraries with the headless
+/// renderer, so the shaders are validated without a display, e.g. on
+/// CI.
+pub fn check_shaders() -> Result<()> {
+    sdl2::hint::set("SDL_VIDEODRIVER", "offscreen");
+    let sdl = sdl2::init().map_err(Error::Sdl)?;
+    let renderer = Renderer::new_headless(&sdl, Vec2::new(1, 1))?;
+
+    check_shader_libraries(&renderer.gl)
+}
+
+/// Runs the closure with the headless renderer. SDL can be initialized
+/// only once at a time, so the tests which render are serialized.
+#[cfg(test)]
+pub fn with_headless_renderer(
+    size: Vec2<u32>,
+    f: impl FnOnce(&mut Renderer),
+) {
+    static SDL_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let _lock = SDL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Doesn't need a display
+    sdl2::hint::set("SDL_VIDEODRIVER", "offscreen");
+    let sdl = sdl2::init().unwrap();
+    let mut renderer = Renderer::new_headless(&sdl, size)
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    f(&mut renderer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_instance_attributes_cover_instance_struct() {
+        let mut attributes: Vec<_> =
+            PRIMITIVE_INSTANCE_ATTRIBUTES.iter().collect();
+        attributes.sort_by_key(|attribute| attribute.offset);
+
+        let mut offset = 0;
+        for attribute in attributes {
+            assert_eq!(attribute.offset, offset, "{}", attribute.name);
+            offset += attribute.size * 4;
+        }
+        assert_eq!(offset, size_of::<PrimitiveInstance>());
+    }
+
+    #[test]
+    fn test_render_to_target_and_read_pixels() {
+        let size = Vec2::new(64, 32);
+        with_headless_renderer(size, |renderer| {
+            let target = renderer.create_render_target(size).unwrap();
+
+            // Red left half in the screen space (y grows up)
+            let rect =
+                Rect::from_bot_left(Vec2::zeros(), Vec2::new(32.0, 32.0));
+            let mut primitive =
+                DrawPrimitive::world_rect(rect, Color::red(1.0));
+            primitive.space = SpaceType::ScreenSpace;
+            renderer.push_primitive(primitive);
+            renderer.render_to_target(target);
+
+            let image = renderer.read_pixels(target);
+            assert_eq!(image.dimensions(), (64, 32));
+            assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+            assert_eq!(image.get_pixel(31, 31).0, [255, 0, 0, 255]);
+            assert_eq!(image.get_pixel(32, 0).0, [0, 0, 0, 255]);
+        });
+    }
+
+    #[test]
+    fn test_shaders_reload() {
+        with_headless_renderer(Vec2::new(1, 1), |renderer| {
+            if let Err(e) = renderer.reload_programs() {
+                panic!("{}", e);
+            }
+        });
+    }
+
+    #[test]
+    fn test_shader_libraries_compile() {
+        with_headless_renderer(Vec2::new(1, 1), |renderer| {
+            if let Err(e) = check_shader_libraries(&renderer.gl) {
+                panic!("{}", e);
+            }
+        });
+    }
+
+    #[test]
+    fn test_moved_texture_frees_its_layer() {
+        with_headless_renderer(Vec2::new(1, 1), |renderer| {
+            let small = RgbaImage::new(3, 4);
+            let handle = renderer.insert_texture(&small).unwrap();
+            let old_slot = renderer.textures[handle.0];
+
+            renderer
+                .replace_texture(handle, &RgbaImage::new(5, 8))
+                .unwrap();
+            let other = renderer.insert_texture(&small).unwrap();
+            let slot = renderer.textures[other.0];
+
+            assert_eq!(
+                (slot.page, slot.layer),
+                (old_slot.page, old_slot.layer)
+            );
+            assert_eq!(renderer.texture_pages[slot.page].n_layers, 1);
+        });
+    }
+
+    #[test]
+    fn test_empty_texture_is_rejected() {
+        with_headless_renderer(Vec2::new(1, 1), |renderer| {
+            let handle =
+                renderer.insert_texture(&RgbaImage::new(2, 2)).unwrap();
+            for (w, h) in [(0, 0), (0, 2), (2, 0)] {
+                let image = RgbaImage::new(w, h);
+                assert!(matches!(
+                    renderer.insert_texture(&image),
+                    Err(Error::EmptyTexture)
+                ));
+                assert!(matches!(
+                    renderer.replace_texture(handle, &image),
+                    Err(Error::EmptyTexture)
+                ));
+            }
+        });
+    }
+
+    #[test]
+    fn test_extrude_image() {
+        let image = RgbaImage::from_fn(2, 2, |x, y| {
+            Rgba([x as u8, y as u8, 0, 255])
+        });
+        let extruded = extrude_image(&image, Vec2::new(4, 3));
+
+        assert_eq!(extruded.dimensions(), (4, 3));
+        assert_eq!(extruded.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(extruded.get_pixel(3, 0).0, [1, 0, 0, 255]);
+        assert_eq!(extruded.get_pixel(0, 2).0, [0, 1, 0, 255]);
+        assert_eq!(extruded.get_pixel(3, 2).0, [1, 1, 0, 255]);
+    }
+
+    fn new_bench_instance(i: usize) -> PrimitiveInstance {
+        let v = i as f32;
+        PrimitiveInstance {
+            xywh: [v, v, 16.0, 16.0],
+            tex_uvwh: [v, v, 16.0, 16.0],
+            tint: [1.0, 1.0, 1.0, 1.0],
+            space: 1,
+            tex_id: 2,
+            transform: [1.0, 0.0, 0.0, 1.0],
+            translation: [v, v],
+            slice_scale: 1.0,
+            ..Default::default()
+        }
+    }
+
+    /// Bytes of each instance field, in the order of the struct fields.
+    fn get_field_bytes(
+        instance: &PrimitiveInstance,
+    ) -> [&[u8]; PRIMITIVE_INSTANCE_ATTRIBUTES.len()] {
+        [
+            cast_slice_to_u8(&instance.xywh),
+            cast_slice_to_u8(&instance.tex_uvwh),
+            cast_slice_to_u8(&instance.tint),
+            cast_slice_to_u8(&instance.flash),
+            cast_slice_to_u8(std::slice::from_ref(&instance.space)),
+            cast_slice_to_u8(std::slice::from_ref(&instance.effect)),
+            cast_slice_to_u8(std::slice::from_ref(&instance.tex_id)),
+            cast_slice_to_u8(&instance.flip),
+            cast_slice_to_u8(&instance.tex_page_layer),
+            cast_slice_to_u8(&instance.transform),
+            cast_slice_to_u8(&instance.translation),
+            cast_slice_to_u8(&instance.slice_insets),
+            cast_slice_to_u8(std::slice::from_ref(&instance.slice_scale)),
+        ]
+    }
+
+    /// Compares the per-frame upload of the instance data: one buffer
+    /// per attribute against the interleaved one. Both upload all the
+    /// instance fields and are uploaded to the GL buffers like in
+    /// `InstanceBuffer::sync_data`.
+    /// Run with `cargo test --release bench -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_instance_upload() {
+        const N_PRIMITIVES: usize = 1 << 14;
+        const N_FRAMES: usize = 200;
+        const N_FIELDS: usize = PRIMITIVE_INSTANCE_ATTRIBUTES.len();
+
+        let instance = new_bench_instance(0);
+        let field_bytes = get_field_bytes(&instance);
+        let n_field_bytes: usize =
+            field_bytes.iter().map(|b| b.len()).sum();
+        assert_eq!(n_field_bytes, size_of::<PrimitiveInstance>());
+
+        with_headless_renderer(Vec2::new(1, 1), |renderer| {
+            let gl = &renderer.gl;
+            let vbos: Vec<_> = (0..N_FIELDS + 1)
+                .map(|_| create_vbo(gl, 0, glow::DYNAMIC_DRAW).unwrap())
+                .collect();
+            let upload = |vbo, data: &[u8]| unsafe {
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+                gl.buffer_data_size(
+                    glow::ARRAY_BUFFER,
+                    data.len() as i32,
+                    glow::DYNAMIC_DRAW,
+                );
+                gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, data);
+            };
+
+            let mut fields: Vec<_> = field_bytes
+                .iter()
+                .map(|bytes| {
+                    Vec::<u8>::with_capacity(N_PRIMITIVES * bytes.len())
+                })
+                .collect();
+            let start = Instant::now();
+            for _ in 0..N_FRAMES {
+                for i in 0..N_PRIMITIVES {
+                    let instance = new_bench_instance(i);
+                    for (field, bytes) in
+                        fields.iter_mut().zip(get_field_bytes(&instance))
+                    {
+                        field.extend_from_slice(bytes);
+                    }
+                }
+
+                for (field, vbo) in fields.iter_mut().zip(&vbos) {
+                    upload(*vbo, field);
+                    field.clear();
+                }
+            }
+            unsafe { gl.finish() };
+            let separate_time = start.elapsed() / N_FRAMES as u32;
+
+            let mut instances =
+                Vec::<PrimitiveInstance>::with_capacity(N_PRIMITIVES);
+            let start = Instant::now();
+            for _ in 0..N_FRAMES {
+                for i in 0..N_PRIMITIVES {
+                    instances.push(new_bench_instance(i));
+                }
+
+                upload(vbos[N_FIELDS], cast_slice_to_u8(&instances));
+                instances.clear();
+            }
+            unsafe { gl.finish() };
+            let interleaved_time = start.elapsed() / N_FRAMES as u32;
+
+            println!(
+                "{} primitives per frame: {:?} separate ({} uploads), \
+                 {:?} interleaved (1 upload)",
+                N_PRIMITIVES, separate_time, N_FIELDS, interleaved_time
+            );
+
+            for vbo in vbos {
+                unsafe { gl.delete_buffer(vbo) };
+            }
+        });
+    }
+}
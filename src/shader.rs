@@ -0,0 +1,194 @@
+//! Shader preprocessor. It resolves `#include "name"` directives, so
+//! every shader pulls only the code it needs. The included files are
+//! relative to the including one, each is included at most once per
+//! shader. The generated sources (e.g. the constants from the Rust
+//! enums) are included by their names and are not read from disk.
+//!
+//! The preprocessed source marks each file by the `#line` directives,
+//! so the compiler reports the original line numbers and the
+//! source-string numbers, which `ShaderSource::map_log` replaces by the
+//! file names.
+use crate::error::{Error, Result};
+use std::fs;
+use std::path::Path;
+
+const INCLUDE_DIRECTIVE: &str = "#include";
+
+pub struct ShaderSource {
+    pub source: String,
+    /// Indexed by the source-string number of the `#line` directives.
+    fps: Vec<String>,
+}
+
+impl ShaderSource {
+    pub fn preprocess(
+        fp: &str,
+        generated: &[(&str, &str)],
+    ) -> Result<Self> {
+        let mut shader = Self {
+            source: String::new(),
+            fps: Vec::new(),
+        };
+        shader.include(fp, generated)?;
+
+        Ok(shader)
+    }
+
+    /// Replaces the source-string numbers in the compiler messages by
+    /// the file names. Drivers format the messages differently: Mesa
+    /// writes `0:12(5): error`, Nvidia `0(12) : error` and AMD
+    /// `ERROR: 0:12: ...`, the other lines are kept as is.
+    pub fn map_log(&self, log: &str) -> String {
+        let mut mapped = String::with_capacity(log.len());
+        for line in log.lines() {
+            mapped.push_str(&self.map_log_line(line));
+            mapped.push('\n');
+        }
+
+        mapped
+    }
+
+    fn map_log_line(&self, line: &str) -> String {
+        let prefix_len = ["ERROR: ", "WARNING: "]
+            .iter()
+            .find(|prefix| line.starts_with(*prefix))
+            .map_or(0, |prefix| prefix.len());
+        let (prefix, rest) = line.split_at(prefix_len);
+
+        let n_digits =
+            rest.chars().take_while(char::is_ascii_digit).count();
+        let is_location = n_digits > 0
+            && rest[n_digits..].starts_with([':', '('])
+            && rest[n_digits + 1..]
+                .starts_with(|c: char| c.is_ascii_digit());
+        let fp = rest[..n_digits]
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| self.fps.get(idx));
+
+        match fp {
+            Some(fp) if is_location => {
+                format!("{}{}{}", prefix, fp, &rest[n_digits..])
+            }
+            _ => line.to_string(),
+        }
+    }
+
+    fn include(
+        &mut self,
+        fp: &str,
+        generated: &[(&str, &str)],
+    ) -> Result<()> {
+        let idx = self.fps.len();
+        self.fps.push(fp.to_string());
+
+        let source = match generated.iter().find(|(name, _)| *name == fp) {
+            Some((_, source)) => source.to_string(),
+            None => fs::read_to_string(fp).map_err(Error::io(fp))?,
+        };
+
+        // The root shader starts with `#version`, nothing can precede it
+        if idx > 0 {
+            self.source.push_str(&format!("#line 1 {}\n", idx));
+        }
+
+        for (i, line) in source.lines().enumerate() {
+            let Some(directive) =
+                line.trim_start().strip_prefix(INCLUDE_DIRECTIVE)
+            else {
+                self.source.push_str(line);
+                self.source.push('\n');
+                continue;
+            };
+
+            let Some(name) = directive
+                .trim()
+                .strip_prefix('"')
+                .and_then(|name| name.strip_suffix('"'))
+            else {
+                return Err(Error::Shader {
+                    fp: fp.to_string(),
+                    log: format!(
+                        "{}:{}: expected #include \"name\"",
+                        fp,
+                        i + 1
+                    ),
+                });
+            };
+
+            let include_fp = if generated
+                .iter()
+                .any(|(generated_name, _)| *generated_name == name)
+            {
+                name.to_string()
+            } else {
+                let dir = Path::new(fp).parent();
+                let dir = dir.unwrap_or(Path::new(""));
+                dir.join(name).to_string_lossy().into_owned()
+            };
+
+            // Keeps the line count, so the directive line still maps to
+            // itself
+            self.source.push('\n');
+            if !self.fps.contains(&include_fp) {
+                self.include(&include_fp, generated)?;
+                self.source.push_str(&format!(
+                    "#line {} {}\n",
+                    i + 2,
+                    idx
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preprocess_includes_once_with_line_mapping() {
+        let generated = [
+            (
+                "main.frag",
+                "#version 450 core\n#include \"a\"\n#include \"b\"\n\
+                 void main() {}\n",
+            ),
+            ("a", "#include \"b\"\nfloat a;\n"),
+            ("b", "float b;\n"),
+        ];
+        let shader =
+            ShaderSource::preprocess("main.frag", &generated).unwrap();
+
+        assert_eq!(
+            shader.source,
+            "#version 450 core\n\n#line 1 1\n\n#line 1 2\nfloat b;\n\
+             #line 2 1\nfloat a;\n#line 3 0\n\nvoid main() {}\n"
+        );
+        assert_eq!(shader.fps, ["main.frag", "a", "b"]);
+    }
+
+    #[test]
+    fn test_map_log() {
+        let shader = ShaderSource {
+            source: String::new(),
+            fps: vec!["main.frag".to_string(), "common.glsl".to_string()],
+        };
+        let log = "0:12(5): error: x\n\
+                   1(3) : error C0000: y\n\
+                   ERROR: 1:7: z\n\
+                   2:1(1): error: unknown file\n\
+                   note: 1 error";
+
+        assert_eq!(
+            shader.map_log(log),
+            "main.frag:12(5): error: x\n\
+             common.glsl(3) : error C0000: y\n\
+             ERROR: common.glsl:7: z\n\
+             2:1(1): error: unknown file\n\
+             note: 1 error\n"
+        );
+    }
+}
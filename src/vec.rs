@@ -364,7 +364,7 @@ impl Rect {
         size: Vec2<f32>,
     ) -> Self {
         let mut center = position;
-        center.x += size.x * 0.5;
+        center.x -= size.x * 0.5;
 
         Self::from_center(center, size)
     }
@@ -603,3 +603,189 @@ impl Rect {
         [center.x, center.y, size.x, size.y]
     }
 }
+
+/// 2D affine transform, maps the point `p` to
+/// `x_axis * p.x + y_axis * p.y + translation`.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub x_axis: Vec2<f32>,
+    pub y_axis: Vec2<f32>,
+    pub translation: Vec2<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            x_axis: Vec2::right(),
+            y_axis: Vec2::up(),
+            translation: Vec2::zeros(),
+        }
+    }
+
+    pub fn from_translation(translation: Vec2<f32>) -> Self {
+        Self {
+            translation,
+            ..Self::identity()
+        }
+    }
+
+    /// Scales and then rotates counter-clockwise (in radians) around
+    /// the pivot point, which stays in place.
+    pub fn from_pivot(
+        pivot: Vec2<f32>,
+        rotation: f32,
+        scale: Vec2<f32>,
+    ) -> Self {
+        let x_axis = Vec2::from_angle(rotation).scale(scale.x);
+        let y_axis = Vec2::from_angle(rotation + 0.5 * PI).scale(scale.y);
+
+        Self::from_axes(pivot, x_axis, y_axis)
+    }
+
+    /// Skews by the angles (in radians) around the pivot point, which
+    /// stays in place. The `skew.x` tilts the y axis towards the x one,
+    /// the `skew.y` tilts the x axis towards the y one.
+    pub fn from_skew(pivot: Vec2<f32>, skew: Vec2<f32>) -> Self {
+        let x_axis = Vec2::new(1.0, skew.y.tan());
+        let y_axis = Vec2::new(skew.x.tan(), 1.0);
+
+        Self::from_axes(pivot, x_axis, y_axis)
+    }
+
+    /// Linear transform by the axes around the pivot point. Other
+    /// linear transforms (e.g. the reflections) can be built from
+    /// their axes this way.
+    pub fn from_axes(
+        pivot: Vec2<f32>,
+        x_axis: Vec2<f32>,
+        y_axis: Vec2<f32>,
+    ) -> Self {
+        let translation =
+            pivot - x_axis.scale(pivot.x) - y_axis.scale(pivot.y);
+
+        Self {
+            x_axis,
+            y_axis,
+            translation,
+        }
+    }
+
+    /// Transform, which applies `self` first and `other` after it.
+    pub fn then(&self, other: Transform) -> Self {
+        Self {
+            x_axis: other.apply_to_vector(self.x_axis),
+            y_axis: other.apply_to_vector(self.y_axis),
+            translation: other.apply(self.translation),
+        }
+    }
+
+    pub fn apply(&self, point: Vec2<f32>) -> Vec2<f32> {
+        self.apply_to_vector(point) + self.translation
+    }
+
+    /// Applies the transform without the translation.
+    pub fn apply_to_vector(&self, vector: Vec2<f32>) -> Vec2<f32> {
+        self.x_axis.scale(vector.x) + self.y_axis.scale(vector.y)
+    }
+
+    /// Columns of the linear part.
+    pub fn to_mat2(self) -> [f32; 4] {
+        [self.x_axis.x, self.x_axis.y, self.y_axis.x, self.y_axis.y]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec2<f32>, expected: Vec2<f32>) {
+        assert!(
+            (actual - expected).len() < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_pivot_stays_in_place() {
+        let pivot = Vec2::new(3.0, -2.0);
+        let transforms = [
+            Transform::from_pivot(pivot, 0.7, Vec2::new(2.0, -0.5)),
+            Transform::from_skew(pivot, Vec2::new(0.3, -0.4)),
+        ];
+
+        for transform in transforms {
+            assert_close(transform.apply(pivot), pivot);
+        }
+    }
+
+    #[test]
+    fn test_from_pivot_scales_then_rotates() {
+        let transform = Transform::from_pivot(
+            Vec2::zeros(),
+            0.5 * PI,
+            Vec2::new(2.0, 1.0),
+        );
+
+        assert_close(
+            transform.apply(Vec2::new(1.0, 0.0)),
+            Vec2::new(0.0, 2.0),
+        );
+        assert_close(
+            transform.apply(Vec2::new(0.0, 1.0)),
+            Vec2::new(-1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn test_from_skew() {
+        let transform =
+            Transform::from_skew(Vec2::zeros(), Vec2::new(0.25 * PI, 0.0));
+
+        assert_close(
+            transform.apply(Vec2::new(1.0, 0.0)),
+            Vec2::new(1.0, 0.0),
+        );
+        assert_close(
+            transform.apply(Vec2::new(0.0, 1.0)),
+            Vec2::new(1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn test_then_applies_self_first() {
+        let scale =
+            Transform::from_pivot(Vec2::zeros(), 0.0, Vec2::new(2.0, 2.0));
+        let translation = Transform::from_translation(Vec2::new(1.0, 0.0));
+        let point = Vec2::new(1.0, 1.0);
+
+        let composed = scale.then(translation);
+        assert_close(
+            composed.apply(point),
+            translation.apply(scale.apply(point)),
+        );
+        assert_close(composed.apply(point), Vec2::new(3.0, 2.0));
+        assert_close(
+            translation.then(scale).apply(point),
+            Vec2::new(4.0, 2.0),
+        );
+    }
+
+    #[test]
+    fn test_to_mat2_is_column_major() {
+        let transform = Transform {
+            x_axis: Vec2::new(1.0, 2.0),
+            y_axis: Vec2::new(3.0, 4.0),
+            translation: Vec2::zeros(),
+        };
+
+        assert_eq!(transform.to_mat2(), [1.0, 2.0, 3.0, 4.0]);
+    }
+}
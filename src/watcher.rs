@@ -0,0 +1,46 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Polls modification times of the files, so it doesn't need any
+/// platform specific notifications.
+pub struct FileWatcher {
+    fps: Vec<String>,
+    mtimes: Vec<Option<SystemTime>>,
+    prev_poll_time: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(fps: &[&str]) -> Self {
+        Self {
+            fps: fps.iter().map(|fp| fp.to_string()).collect(),
+            mtimes: fps.iter().map(|fp| get_mtime(fp)).collect(),
+            prev_poll_time: Instant::now(),
+        }
+    }
+
+    /// Files modified since the previous poll. Removed files are not
+    /// reported until they appear again.
+    pub fn poll(&mut self) -> Vec<&str> {
+        let mut changed_fps = Vec::new();
+        if self.prev_poll_time.elapsed() < POLL_INTERVAL {
+            return changed_fps;
+        }
+
+        self.prev_poll_time = Instant::now();
+        for (fp, mtime) in self.fps.iter().zip(self.mtimes.iter_mut()) {
+            let new_mtime = get_mtime(fp);
+            if new_mtime.is_some() && new_mtime != *mtime {
+                changed_fps.push(fp.as_str());
+            }
+            *mtime = new_mtime;
+        }
+
+        changed_fps
+    }
+}
+
+fn get_mtime(fp: &str) -> Option<SystemTime> {
+    fs::metadata(fp).and_then(|meta| meta.modified()).ok()
+}